@@ -2,7 +2,9 @@ use serde_json::Value;
 use serenity::{
     client::bridge::gateway::event::ShardStageUpdateEvent,
     model::{
-        channel::{Channel, ChannelCategory, GuildChannel, Message, PrivateChannel, Reaction},
+        channel::{
+            Channel, ChannelCategory, GuildChannel, Message, PrivateChannel, Reaction, ReactionType,
+        },
         event::{
             ChannelPinsUpdateEvent, MessageUpdateEvent, PresenceUpdateEvent, ResumedEvent,
             TypingStartEvent, VoiceServerUpdateEvent,
@@ -15,7 +17,7 @@ use serenity::{
     },
     prelude::RwLock,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 #[derive(Clone, Debug)]
 pub enum BackendMsg {
@@ -29,6 +31,10 @@ pub enum BackendMsg {
     ChannelRecipientAdd(ChannelId, User),
     ChannelRecipientRm(ChannelId, User),
     ChannelUpdate(Option<Channel>, Channel),
+    /// The DM we asked to open with someone
+    DmOpened(Arc<RwLock<PrivateChannel>>),
+    /// Where a download was saved to, or why it failed
+    DownloadFinished(PathBuf, Result<(), String>),
     GuildBanAdd(GuildId, User),
     GuildBanRm(GuildId, User),
     GuildCreate(Guild, bool),
@@ -44,19 +50,33 @@ pub enum BackendMsg {
     GuildRoleUpdate(GuildId, Option<Role>, Role),
     GuildUnavailable(GuildId),
     GuildUpdate(Option<Arc<RwLock<Guild>>>, PartialGuild),
+    /// We left the group DM
+    GroupLeft(ChannelId),
     MessageAdd(Message),
+    MessageHistory(ChannelId, Vec<Message>),
     MessageRm(ChannelId, MessageId),
     MessageRmBulk(ChannelId, Vec<MessageId>),
+    /// How sending, or editing the message with the id, went, along with the content so
+    /// it can be put back if it failed
+    MessageSent(ChannelId, Option<MessageId>, String, Result<(), String>),
     MessageUpdate(Option<Message>, Option<Message>, MessageUpdateEvent),
     ReactionAdd(Reaction),
     ReactionRm(Reaction),
     ReactionRmAll(ChannelId, MessageId),
+    /// The users who reacted to a message with an emoji
+    ReactionUsers(ChannelId, MessageId, ReactionType, Vec<User>),
     PresenceReplace(Vec<Presence>),
+    /// Why something we asked Discord for failed, to be shown as it is
+    RequestFailed(String),
+    /// The DMs fetched over http, which may include some the gateway didn't mention
+    PrivateChannels(Vec<PrivateChannel>),
     PresenceUpdate(PresenceUpdateEvent),
     Ready(Ready, crate::ui::InitializationState),
     Resume(ResumedEvent),
     ShardStageUpdate(ShardStageUpdateEvent),
     TypingStart(TypingStartEvent),
+    /// The state of one file of an upload, by upload id and the file's index within it
+    UploadProgress(u64, usize, super::UploadState),
     UserUpdate(CurrentUser, CurrentUser),
     VoiceServerUpdate(VoiceServerUpdateEvent),
     VoiceStateUpdate(Option<GuildId>, Option<VoiceState>, VoiceState),
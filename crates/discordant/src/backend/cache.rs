@@ -1,39 +1,502 @@
+use super::BackendMsg;
+use crate::ui::{GuildState, InitializationState};
 use dashmap::DashMap;
+use serenity::model::{
+    channel::{Channel, ChannelCategory, ChannelType, Group, GuildChannel, PrivateChannel},
+    gateway::{Activity, ActivityType, Presence},
+    guild::{Emoji, Member, Role},
+    id::{EmojiId, RoleId},
+    user::{OnlineStatus, User},
+};
+use std::{collections::HashMap, ops::Deref};
 
+/// The client's own view of everything it knows about, kept up to date from backend
+/// events so the ui never has to hold onto serenity's shared models
+#[derive(Default)]
 pub struct Cache {
     users: DashMap<u64, UserData>,
     guilds: DashMap<u64, GuildData>,
+    /// The guild of each guild channel, so channels are found without going through
+    /// every guild
+    channel_guilds: DashMap<u64, u64>,
+    /// DMs and group DMs
     dms: DashMap<u64, ChannelData>,
+    presences: DashMap<u64, PresenceData>,
 }
 
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn user(&self, user_id: u64) -> Option<impl Deref<Target = UserData> + '_> {
+        self.users.get(&user_id)
+    }
+
+    /// The ids of all cached users, in no particular order
+    pub fn user_ids(&self) -> Vec<u64> {
+        self.users.iter().map(|user| *user.key()).collect()
+    }
+
+    pub fn guild(&self, guild_id: u64) -> Option<impl Deref<Target = GuildData> + '_> {
+        self.guilds.get(&guild_id)
+    }
+
+    /// What a user goes by, their nickname if they're a member of the guild with one
+    pub fn display_name(&self, guild_id: Option<u64>, user_id: u64) -> Option<String> {
+        let nickname = guild_id
+            .and_then(|guild_id| self.guild(guild_id))
+            .and_then(|guild| guild.members.get(&user_id)?.nickname.clone());
+
+        nickname.or_else(|| self.user(user_id).map(|user| user.name.clone()))
+    }
+
+    /// The ids of all cached guilds, in no particular order
+    pub fn guild_ids(&self) -> Vec<u64> {
+        self.guilds.iter().map(|guild| *guild.key()).collect()
+    }
+
+    pub fn dm(&self, channel_id: u64) -> Option<impl Deref<Target = ChannelData> + '_> {
+        self.dms.get(&channel_id)
+    }
+
+    /// The ids of all DMs and group DMs, in no particular order
+    pub fn dm_ids(&self) -> Vec<u64> {
+        self.dms.iter().map(|dm| *dm.key()).collect()
+    }
+
+    /// What a DM is called, unnamed groups go by the names of their recipients
+    pub fn dm_name(&self, dm: &ChannelData) -> String {
+        if !dm.name.is_empty() {
+            return dm.name.clone();
+        }
+
+        let names = dm
+            .recipients
+            .iter()
+            .filter_map(|user_id| self.user(*user_id).map(|user| user.name.clone()))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            "Unnamed Group".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+
+    /// Forgets a group DM we left, the gateway doesn't tell about it
+    pub fn remove_dm(&self, channel_id: u64) {
+        self.dms.remove(&channel_id);
+    }
+
+    /// Whether a user is online, as far as any guild or DM we share tells
+    pub fn status(&self, user_id: u64) -> OnlineStatus {
+        self.presences
+            .get(&user_id)
+            .map_or(OnlineStatus::Offline, |presence| presence.status)
+    }
+
+    pub fn presence(&self, user_id: u64) -> Option<PresenceData> {
+        self.presences
+            .get(&user_id)
+            .map(|presence| presence.clone())
+    }
+
+    /// A guild channel or DM by its id, wherever it is
+    pub fn channel(&self, channel_id: u64) -> Option<ChannelData> {
+        let guild_id = self.guild_with_channel(channel_id);
+        let in_guild = guild_id
+            .and_then(|guild_id| self.guilds.get(&guild_id))
+            .and_then(|guild| guild.channels.get(&channel_id).cloned());
+
+        in_guild.or_else(|| self.dms.get(&channel_id).map(|dm| (*dm).clone()))
+    }
+
+    /// Fills the cache with the state the client started out with
+    pub fn load(&self, state: &InitializationState) {
+        self.insert_user(&User::from(state.user.clone()));
+
+        for guild in state.guilds.iter() {
+            self.insert_guild(guild);
+        }
+
+        for channel in state.private_channels.iter() {
+            self.insert_dm(channel);
+        }
+
+        for group in state.groups.iter() {
+            self.insert_group(group);
+        }
+    }
+
+    /// Applies a backend event to the cache, events that don't touch anything cached are
+    /// ignored
+    pub fn update(&self, msg: &BackendMsg) {
+        match msg {
+            BackendMsg::Ready(ready, state) => {
+                self.load(state);
+                for presence in ready.presences.values() {
+                    self.insert_presence(presence);
+                }
+            }
+            BackendMsg::PresenceReplace(presences) => {
+                for presence in presences {
+                    self.insert_presence(presence);
+                }
+            }
+            BackendMsg::PresenceUpdate(event) => self.insert_presence(&event.presence),
+            BackendMsg::UserUpdate(_, user) => {
+                self.insert_user(&User::from(user.clone()));
+            }
+            BackendMsg::MessageAdd(message) => {
+                self.insert_user(&message.author);
+                self.set_last_message(message.channel_id.0, message.id.0);
+            }
+            BackendMsg::MessageHistory(channel_id, messages) => {
+                if let Some(newest) = messages.last() {
+                    self.set_last_message(channel_id.0, newest.id.0);
+                }
+            }
+
+            BackendMsg::GuildCreate(guild, _) => self.insert_guild(&GuildState::from(guild)),
+            BackendMsg::GuildUpdate(_, guild) => {
+                if let Some(mut cached) = self.guilds.get_mut(&guild.id.0) {
+                    cached.name = guild.name.clone();
+                    cached.icon = guild.icon_url();
+                    cached.owner_id = guild.owner_id.0;
+                    cached.roles = roles(&guild.roles);
+                    cached.emojis = emojis(&guild.emojis);
+                }
+            }
+            BackendMsg::GuildDel(guild, _) => self.remove_guild(guild.id.0),
+            BackendMsg::GuildEmojiUpdate(guild_id, updated) => {
+                if let Some(mut guild) = self.guilds.get_mut(&guild_id.0) {
+                    guild.emojis = emojis(updated);
+                }
+            }
+
+            BackendMsg::ChannelCreate(channel) => self.insert_channel(&channel.read()),
+            BackendMsg::ChannelDelete(channel) => {
+                let channel = channel.read();
+                if let Some(mut guild) = self.guilds.get_mut(&channel.guild_id.0) {
+                    guild.channels.remove(&channel.id.0);
+                }
+                self.channel_guilds.remove(&channel.id.0);
+            }
+            BackendMsg::ChannelUpdate(_, channel) => match channel {
+                Channel::Guild(channel) => self.insert_channel(&channel.read()),
+                Channel::Category(category) => {
+                    let category = category.read();
+                    if let Some(guild_id) = self.guild_with_channel(category.id.0) {
+                        if let Some(mut guild) = self.guilds.get_mut(&guild_id) {
+                            guild
+                                .channels
+                                .insert(category.id.0, ChannelData::from(&*category));
+                        }
+                    }
+                }
+                Channel::Private(channel) => self.insert_dm(&channel.read()),
+                Channel::Group(group) => self.insert_group(&group.read()),
+                _ => {}
+            },
+            // Categories don't carry the id of their guild, so new ones only show up once
+            // they're sent along with the guild or updated
+            BackendMsg::CategoryCreate(_) => {}
+            BackendMsg::CategoryDelete(category) => {
+                let category_id = category.read().id.0;
+                if let Some(guild_id) = self.guild_with_channel(category_id) {
+                    if let Some(mut guild) = self.guilds.get_mut(&guild_id) {
+                        guild.channels.remove(&category_id);
+                    }
+                }
+                self.channel_guilds.remove(&category_id);
+            }
+            BackendMsg::PrivateChannelCreate(channel) => self.insert_dm(&channel.read()),
+            BackendMsg::PrivateChannels(channels) => {
+                for channel in channels {
+                    self.insert_dm(channel);
+                }
+            }
+            BackendMsg::ChannelRecipientAdd(channel_id, user) => {
+                self.insert_user(user);
+                if let Some(mut group) = self.dms.get_mut(&channel_id.0) {
+                    if !group.recipients.contains(&user.id.0) {
+                        group.recipients.push(user.id.0);
+                    }
+                }
+            }
+            BackendMsg::ChannelRecipientRm(channel_id, user) => {
+                if let Some(mut group) = self.dms.get_mut(&channel_id.0) {
+                    group.recipients.retain(|user_id| *user_id != user.id.0);
+                }
+            }
+
+            BackendMsg::GuildMemberAdd(_, member) | BackendMsg::GuildMemberUpdate(_, member) => {
+                self.insert_member(member)
+            }
+            BackendMsg::GuildMemberRm(guild_id, user, _) => {
+                if let Some(mut guild) = self.guilds.get_mut(&guild_id.0) {
+                    guild.members.remove(&user.id.0);
+                }
+            }
+            BackendMsg::GuildMembersOffline(_, members) => {
+                for member in members.values() {
+                    self.insert_member(member);
+                }
+            }
+
+            BackendMsg::GuildRoleAdd(guild_id, role)
+            | BackendMsg::GuildRoleUpdate(guild_id, _, role) => {
+                if let Some(mut guild) = self.guilds.get_mut(&guild_id.0) {
+                    guild.roles.insert(role.id.0, RoleData::from(role));
+                }
+            }
+            BackendMsg::GuildRoleRm(guild_id, role_id, _) => {
+                if let Some(mut guild) = self.guilds.get_mut(&guild_id.0) {
+                    guild.roles.remove(&role_id.0);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn insert_user(&self, user: &User) {
+        self.users.insert(user.id.0, UserData::from(user));
+    }
+
+    fn insert_guild(&self, guild: &GuildState) {
+        for member in guild.members.iter() {
+            self.insert_user(&member.user.read());
+        }
+
+        // Channels deleted while the guild was unavailable aren't sent along with it
+        self.remove_guild(guild.id.0);
+        for channel_id in guild.channels.keys() {
+            self.channel_guilds.insert(channel_id.0, guild.id.0);
+        }
+        self.guilds.insert(
+            guild.id.0,
+            GuildData {
+                name: guild.name.clone(),
+                icon: guild.icon_url.clone(),
+                owner_id: guild.owner_id.0,
+                members: guild
+                    .members
+                    .iter()
+                    .map(MemberData::from)
+                    .map(|member| (member.user_id, member))
+                    .collect(),
+                roles: roles(&guild.roles),
+                emojis: emojis(&guild.emojis),
+                channels: guild
+                    .channels
+                    .iter()
+                    .map(|(channel_id, channel)| (channel_id.0, ChannelData::from(channel)))
+                    .collect(),
+            },
+        );
+    }
+
+    fn remove_guild(&self, guild_id: u64) {
+        if let Some((_, guild)) = self.guilds.remove(&guild_id) {
+            for channel_id in guild.channels.keys() {
+                self.channel_guilds.remove(channel_id);
+            }
+        }
+    }
+
+    fn insert_member(&self, member: &Member) {
+        let user_id = {
+            let user = member.user.read();
+            self.insert_user(&user);
+            user.id.0
+        };
+
+        if let Some(mut guild) = self.guilds.get_mut(&member.guild_id.0) {
+            guild.members.insert(user_id, MemberData::from(member));
+        }
+    }
+
+    fn insert_channel(&self, channel: &GuildChannel) {
+        if let Some(mut guild) = self.guilds.get_mut(&channel.guild_id.0) {
+            guild
+                .channels
+                .insert(channel.id.0, ChannelData::from(channel));
+            self.channel_guilds.insert(channel.id.0, channel.guild_id.0);
+        }
+    }
+
+    fn insert_dm(&self, channel: &PrivateChannel) {
+        let recipient = channel.recipient.read();
+        self.insert_user(&recipient);
+
+        self.dms.insert(
+            channel.id.0,
+            ChannelData {
+                name: recipient.name.clone(),
+                kind: ChannelKind::Private,
+                category_id: None,
+                position: 0,
+                topic: None,
+                nsfw: false,
+                slow_mode_rate: None,
+                user_limit: None,
+                last_message_id: channel.last_message_id.map(|message_id| message_id.0),
+                recipients: vec![recipient.id.0],
+            },
+        );
+    }
+
+    /// Unnamed groups are left without a name, see `dm_name`
+    fn insert_group(&self, group: &Group) {
+        for recipient in group.recipients.values() {
+            self.insert_user(&recipient.read());
+        }
+
+        self.dms.insert(
+            group.channel_id.0,
+            ChannelData {
+                name: group.name.clone().unwrap_or_default(),
+                kind: ChannelKind::Group,
+                category_id: None,
+                position: 0,
+                topic: None,
+                nsfw: false,
+                slow_mode_rate: None,
+                user_limit: None,
+                last_message_id: group.last_message_id.map(|message_id| message_id.0),
+                recipients: group.recipients.keys().map(|user_id| user_id.0).collect(),
+            },
+        );
+    }
+
+    fn insert_presence(&self, presence: &Presence) {
+        if let Some(user) = &presence.user {
+            self.insert_user(&user.read());
+        }
+
+        self.presences
+            .insert(presence.user_id.0, PresenceData::from(presence));
+    }
+
+    /// Moves the newest message of a channel forward, history may well be older
+    fn set_last_message(&self, channel_id: u64, message_id: u64) {
+        let advance = |channel: &mut ChannelData| {
+            if channel
+                .last_message_id
+                .map_or(true, |last| last < message_id)
+            {
+                channel.last_message_id = Some(message_id);
+            }
+        };
+
+        match self.guild_with_channel(channel_id) {
+            Some(guild_id) => {
+                if let Some(mut guild) = self.guilds.get_mut(&guild_id) {
+                    if let Some(channel) = guild.channels.get_mut(&channel_id) {
+                        advance(channel);
+                    }
+                }
+            }
+            None => {
+                if let Some(mut dm) = self.dms.get_mut(&channel_id) {
+                    advance(&mut dm);
+                }
+            }
+        }
+    }
+
+    /// The id of the guild a channel belongs to, DMs belong to none
+    pub fn guild_with_channel(&self, channel_id: u64) -> Option<u64> {
+        self.channel_guilds
+            .get(&channel_id)
+            .map(|guild_id| *guild_id)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct UserData {
-    name: String,
-    discriminator: u16,
-    avatar: Option<File>,
-    bot: bool,
+    pub name: String,
+    pub discriminator: u16,
+    /// The url of the user's avatar, or of the default avatar if they haven't set one
+    pub avatar: String,
+    pub bot: bool,
 }
 
+impl From<&User> for UserData {
+    fn from(user: &User) -> Self {
+        Self {
+            name: user.name.clone(),
+            discriminator: user.discriminator,
+            avatar: user.face(),
+            bot: user.bot,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct GuildData {
-    name: String,
-    splash: Option<File>,
-    banner: Option<File>,
-    owner_id: u64,
-    icon: Option<File>,
-    members: DashMap<u64, MemberData>,
-    roles: DashMap<u64, RoleData>,
-    channels: DashMap<u64, ChannelData>,
+    pub name: String,
+    /// The url of the guild's icon
+    pub icon: Option<String>,
+    pub owner_id: u64,
+    pub members: HashMap<u64, MemberData>,
+    pub roles: HashMap<u64, RoleData>,
+    pub emojis: HashMap<u64, EmojiData>,
+    pub channels: HashMap<u64, ChannelData>,
 }
 
+#[derive(Debug, Clone)]
 pub struct ChannelData {
-    name: String,
-    kind: ChannelKind,
-    position: i64,
-    topic: Option<String>,
-    nsfw: bool,
-    slow_mode_rate: Option<u64>,
-    user_limit: Option<u64>,
+    pub name: String,
+    pub kind: ChannelKind,
+    pub category_id: Option<u64>,
+    pub position: i64,
+    pub topic: Option<String>,
+    pub nsfw: bool,
+    pub slow_mode_rate: Option<u64>,
+    pub user_limit: Option<u64>,
+    /// The id of the newest message sent to the channel
+    pub last_message_id: Option<u64>,
+    /// Who a DM or group DM is with, guild channels have none
+    pub recipients: Vec<u64>,
+}
+
+impl From<&GuildChannel> for ChannelData {
+    fn from(channel: &GuildChannel) -> Self {
+        Self {
+            name: channel.name.clone(),
+            kind: ChannelKind::from(channel.kind),
+            category_id: channel.category_id.map(|category_id| category_id.0),
+            position: channel.position,
+            topic: channel.topic.clone(),
+            nsfw: channel.nsfw,
+            slow_mode_rate: channel.slow_mode_rate,
+            user_limit: channel.user_limit,
+            last_message_id: channel.last_message_id.map(|message_id| message_id.0),
+            recipients: Vec::new(),
+        }
+    }
 }
 
+impl From<&ChannelCategory> for ChannelData {
+    fn from(category: &ChannelCategory) -> Self {
+        Self {
+            name: category.name.clone(),
+            kind: ChannelKind::Category,
+            category_id: category.category_id.map(|category_id| category_id.0),
+            position: category.position,
+            topic: None,
+            nsfw: category.nsfw,
+            slow_mode_rate: None,
+            user_limit: None,
+            last_message_id: None,
+            recipients: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ChannelKind {
     Text,
     Private,
@@ -44,17 +507,106 @@ pub enum ChannelKind {
     Store,
 }
 
+impl From<ChannelType> for ChannelKind {
+    fn from(kind: ChannelType) -> Self {
+        match kind {
+            ChannelType::Private => ChannelKind::Private,
+            ChannelType::Voice => ChannelKind::Voice,
+            ChannelType::Group => ChannelKind::Group,
+            ChannelType::Category => ChannelKind::Category,
+            ChannelType::News => ChannelKind::News,
+            ChannelType::Store => ChannelKind::Store,
+            _ => ChannelKind::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PresenceData {
+    pub status: OnlineStatus,
+    /// What the user is up to, like "Playing Chess"
+    pub activity: Option<String>,
+}
+
+impl From<&Presence> for PresenceData {
+    fn from(presence: &Presence) -> Self {
+        Self {
+            status: presence.status,
+            activity: presence.activity.as_ref().map(activity_text),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RoleData {
-    color: (u8, u8, u8),
-    hoist: bool,
-    name: String,
-    position: i64,
+    pub color: (u8, u8, u8),
+    pub hoist: bool,
+    pub name: String,
+    pub position: i64,
 }
 
+impl From<&Role> for RoleData {
+    fn from(role: &Role) -> Self {
+        Self {
+            color: role.colour.tuple(),
+            hoist: role.hoist,
+            name: role.name.clone(),
+            position: role.position,
+        }
+    }
+}
+
+/// A guild's custom emoji
+#[derive(Debug, Clone)]
+pub struct EmojiData {
+    pub name: String,
+    pub animated: bool,
+}
+
+impl From<&Emoji> for EmojiData {
+    fn from(emoji: &Emoji) -> Self {
+        Self {
+            name: emoji.name.clone(),
+            animated: emoji.animated,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct MemberData {
-    user_id: u64,
-    nickname: Option<String>,
-    roles: Vec<u64>,
+    pub user_id: u64,
+    pub nickname: Option<String>,
+    pub roles: Vec<u64>,
+}
+
+impl From<&Member> for MemberData {
+    fn from(member: &Member) -> Self {
+        Self {
+            user_id: member.user.read().id.0,
+            nickname: member.nick.clone(),
+            roles: member.roles.iter().map(|role_id| role_id.0).collect(),
+        }
+    }
+}
+
+fn roles(roles: &HashMap<RoleId, Role>) -> HashMap<u64, RoleData> {
+    roles
+        .iter()
+        .map(|(role_id, role)| (role_id.0, RoleData::from(role)))
+        .collect()
+}
+
+fn activity_text(activity: &Activity) -> String {
+    match activity.kind {
+        ActivityType::Streaming => format!("Streaming {}", activity.name),
+        ActivityType::Listening => format!("Listening to {}", activity.name),
+        _ => format!("Playing {}", activity.name),
+    }
 }
 
-pub struct File(Vec<u8>);
+fn emojis(emojis: &HashMap<EmojiId, Emoji>) -> HashMap<u64, EmojiData> {
+    emojis
+        .iter()
+        .map(|(emoji_id, emoji)| (emoji_id.0, EmojiData::from(emoji)))
+        .collect()
+}
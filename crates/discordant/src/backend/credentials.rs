@@ -0,0 +1,268 @@
+use ring::{
+    aead::{self, Aad, Nonce, OpeningKey, SealingKey, CHACHA20_POLY1305, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const KEY_LEN: usize = 32;
+/// The id of the account logged in to with `DISCORD_TOKEN`, stored accounts never get it
+pub const TEMPORARY_ID: u64 = u64::MAX;
+
+/// An account we're logged in to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    /// Ours alone, it tells apart the accounts' stores
+    pub id: u64,
+    /// Only known once Discord accepted the token
+    pub user_id: u64,
+    pub name: String,
+    pub token: String,
+    /// Whether the account stays connected, to be notified about it, while another one
+    /// is shown
+    #[serde(default)]
+    pub keep_connected: bool,
+    /// Logged in with `DISCORD_TOKEN`, which isn't stored
+    #[serde(skip)]
+    pub temporary: bool,
+}
+
+impl Account {
+    pub fn new(id: u64, token: String) -> Self {
+        Self {
+            id,
+            user_id: 0,
+            name: String::new(),
+            token,
+            keep_connected: false,
+            temporary: false,
+        }
+    }
+}
+
+/// The accounts we're logged in to, the one shown last first
+pub fn load_accounts() -> Vec<Account> {
+    let decrypted = match load() {
+        Some(decrypted) => decrypted,
+        None => return Vec::new(),
+    };
+
+    match serde_json::from_slice(&decrypted) {
+        Ok(accounts) => accounts,
+        // Only a single token was stored before there could be several accounts
+        Err(_) => String::from_utf8(decrypted)
+            .map(|token| vec![Account::new(0, token)])
+            .unwrap_or_default(),
+    }
+}
+
+/// Stores the accounts encrypted in the config directory, replacing those stored before.
+/// The key they're encrypted with is kept apart from them, in the system keyring when
+/// built with the `keyring` feature or else in a file of the data directory only we can
+/// read
+pub fn save_accounts(accounts: &[Account]) -> Result<(), String> {
+    let encoded = serde_json::to_vec(accounts).map_err(|err| err.to_string())?;
+    save(&encoded)
+}
+
+/// Wipes the stored accounts along with the key they were encrypted with
+pub fn forget_accounts() {
+    if let Some(path) = credentials_path() {
+        remove(&path);
+    }
+
+    #[cfg(feature = "keyring")]
+    {
+        if let Err(err) = keyring_entry().delete_password() {
+            eprintln!("Credentials Error: {:?}", err);
+        }
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        if let Some(path) = key_path() {
+            remove(&path);
+        }
+    }
+}
+
+fn load() -> Option<Vec<u8>> {
+    let path = credentials_path()?;
+    let mut sealed = match fs::read(&path) {
+        Ok(sealed) => sealed,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            eprintln!(
+                "Credentials Error: Failed to read {}: {:?}",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    };
+    let key = load_key()?;
+
+    if sealed.len() < NONCE_LEN {
+        eprintln!("Credentials Error: {} is corrupted", path.display());
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at_mut(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let key = OpeningKey::new(&CHACHA20_POLY1305, &key).ok()?;
+
+    match aead::open_in_place(&key, nonce, Aad::empty(), 0, ciphertext) {
+        Ok(decrypted) => Some(decrypted.to_vec()),
+        Err(_) => {
+            eprintln!("Credentials Error: {} can't be decrypted", path.display());
+            None
+        }
+    }
+}
+
+fn save(contents: &[u8]) -> Result<(), String> {
+    let path = credentials_path().ok_or("No config directory to keep credentials in")?;
+    let key = match load_key() {
+        Some(key) => key,
+        None => create_key()?,
+    };
+
+    let rng = SystemRandom::new();
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut nonce)
+        .map_err(|_| "Failed to generate a nonce")?;
+
+    let key = SealingKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "Invalid key")?;
+    let mut sealed = contents.to_vec();
+    sealed.extend(vec![0; CHACHA20_POLY1305.tag_len()]);
+    let len = aead::seal_in_place(
+        &key,
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut sealed,
+        CHACHA20_POLY1305.tag_len(),
+    )
+    .map_err(|_| "Failed to encrypt the credentials")?;
+    sealed.truncate(len);
+
+    let mut file = nonce.to_vec();
+    file.extend(sealed);
+    write_private(&path, &file)
+}
+
+fn credentials_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("discordant").join("credentials"))
+}
+
+fn create_key() -> Result<Vec<u8>, String> {
+    let mut key = vec![0; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "Failed to generate a key")?;
+
+    save_key(&key)?;
+    Ok(key)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry() -> keyring::Keyring<'static> {
+    keyring::Keyring::new("discordant", "token-key")
+}
+
+#[cfg(feature = "keyring")]
+fn load_key() -> Option<Vec<u8>> {
+    match keyring_entry().get_password() {
+        Ok(key) => from_hex(&key),
+        Err(keyring::KeyringError::NoPasswordFound) => None,
+        Err(err) => {
+            eprintln!("Credentials Error: {:?}", err);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn save_key(key: &[u8]) -> Result<(), String> {
+    keyring_entry()
+        .set_password(&to_hex(key))
+        .map_err(|err| format!("Failed to store the key in the keyring: {}", err))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn key_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("discordant").join("credentials-key"))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn load_key() -> Option<Vec<u8>> {
+    let path = key_path()?;
+    match fs::read_to_string(&path) {
+        Ok(key) => from_hex(key.trim()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            eprintln!(
+                "Credentials Error: Failed to read {}: {:?}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn save_key(key: &[u8]) -> Result<(), String> {
+    let path = key_path().ok_or("No data directory to keep the key in")?;
+    write_private(&path, to_hex(key).as_bytes())
+}
+
+/// Writes a file only the user can read, creating its directory if needed
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    #[cfg(unix)]
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let failed = |err: io::Error| format!("Failed to write {}: {}", path.display(), err);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(failed)
+}
+
+fn remove(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => eprintln!(
+            "Credentials Error: Failed to remove {}: {:?}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,95 @@
+use crate::ui::images;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// How many bytes of fetched images are kept on disk
+const CAPACITY: u64 = 512 * 1024 * 1024;
+/// Eviction goes this far below the capacity, so it doesn't run again on the next write
+const EVICT_TO: u64 = CAPACITY / 4 * 3;
+
+/// Fetched images kept on disk between runs. Once they take up more than `CAPACITY`,
+/// those cached longest ago are removed
+pub struct DiskCache {
+    dir: PathBuf,
+    /// How many bytes the cached files take up, counted again on every eviction
+    size: AtomicU64,
+}
+
+impl DiskCache {
+    /// Opens the cache in the cache directory, logging why if there's none to use
+    pub fn open() -> Option<Self> {
+        let dir = dirs::cache_dir()?.join("discordant").join("images");
+        if let Err(err) = fs::create_dir_all(&dir) {
+            eprintln!("Cache Dir Error: {:?}", err);
+            return None;
+        }
+
+        let cache = Self {
+            dir,
+            size: AtomicU64::new(0),
+        };
+        // Counts what earlier runs left behind
+        cache.evict();
+
+        Some(cache)
+    }
+
+    pub fn read(&self, url: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(url)).ok()
+    }
+
+    pub fn write(&self, url: &str, file: &[u8]) {
+        if let Err(err) = fs::write(self.path(url), file) {
+            eprintln!("Cache Write Error: {:?}", err);
+            return;
+        }
+
+        let len = file.len() as u64;
+        if self.size.fetch_add(len, Ordering::SeqCst) + len > CAPACITY {
+            self.evict();
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}", images::hash(url)))
+    }
+
+    /// Removes the files written longest ago until the rest take up at most `EVICT_TO`
+    /// bytes, or leaves them all if they fit in the capacity
+    fn evict(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("Cache Dir Error: {:?}", err);
+                return;
+            }
+        };
+        let mut files = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut size = files.iter().map(|(_, len, _)| len).sum::<u64>();
+        if size > CAPACITY {
+            files.sort_by_key(|(modified, _, _)| *modified);
+            for (_, len, path) in files {
+                if size <= EVICT_TO {
+                    break;
+                }
+
+                match fs::remove_file(&path) {
+                    Ok(()) => size -= len,
+                    Err(err) => eprintln!("Cache Evict Error: {:?}", err),
+                }
+            }
+        }
+
+        self.size.store(size, Ordering::SeqCst);
+    }
+}
@@ -1,7 +1,10 @@
-use super::{BackendMsg, SenderKey};
+use super::{store::Store, BackendMsg, SenderKey};
+use crate::ui::{GuildState, InitializationState};
+use futures::channel::mpsc::Sender;
 use serde_json::Value;
 use serenity::{
     client::{bridge::gateway::event::ShardStageUpdateEvent, Context, EventHandler},
+    http::Http,
     model::{
         channel::{Channel, ChannelCategory, GuildChannel, Message, PrivateChannel, Reaction},
         event::{
@@ -9,16 +12,129 @@ use serenity::{
             TypingStartEvent, VoiceServerUpdateEvent,
         },
         gateway::{Presence, Ready},
-        guild::{Emoji, Guild, Member, PartialGuild, Role},
+        guild::{Emoji, Guild, GuildStatus, Member, PartialGuild, Role},
         id::{ChannelId, EmojiId, GuildId, MessageId, RoleId, UserId},
         user::{CurrentUser, User},
         voice::VoiceState,
     },
-    prelude::RwLock,
+    prelude::{Mutex, RwLock},
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How long to wait for the guilds that were unavailable in `Ready` before fetching them
+/// over REST instead
+const GUILD_CREATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Handler {
+    startup: Arc<Mutex<Option<Startup>>>,
+    /// Counts the `Ready`s so far, telling a startup apart from those of earlier sessions
+    generation: AtomicU64,
+    store: Option<Arc<Store>>,
+}
+
+impl Handler {
+    pub fn new(store: Option<Arc<Store>>) -> Self {
+        Self {
+            startup: Arc::new(Mutex::new(None)),
+            generation: AtomicU64::new(0),
+            store,
+        }
+    }
+}
+
+/// The initial state as it's pieced together from the gateway, guilds which were
+/// unavailable in `Ready` are filled in as their `GuildCreate`s arrive
+struct Startup {
+    /// The session's `Ready` was the handler's nth
+    generation: u64,
+    ready: Ready,
+    guilds: Vec<GuildState>,
+    pending: HashSet<GuildId>,
+    store: Option<Arc<Store>>,
+}
 
-pub struct Handler;
+impl Startup {
+    /// Hands the assembled state to the ui and the store, keeping the guilds in the order
+    /// `Ready` listed them
+    fn finish(mut self, mut sender: Sender<BackendMsg>) {
+        let order = self
+            .ready
+            .guilds
+            .iter()
+            .map(GuildStatus::id)
+            .collect::<Vec<_>>();
+        self.guilds.sort_by_key(|guild| {
+            order
+                .iter()
+                .position(|guild_id| *guild_id == guild.id)
+                .unwrap_or_else(|| order.len())
+        });
+
+        let private_channels = self
+            .ready
+            .private_channels
+            .values()
+            .filter_map(|channel| match channel {
+                Channel::Private(channel) => Some(channel.read().clone()),
+                _ => None,
+            })
+            .collect();
+        let groups = self
+            .ready
+            .private_channels
+            .values()
+            .filter_map(|channel| match channel {
+                Channel::Group(group) => Some(group.read().clone()),
+                _ => None,
+            })
+            .collect();
+
+        let state = InitializationState {
+            guilds: self.guilds,
+            private_channels,
+            groups,
+            user: self.ready.user.clone(),
+        };
+        if let Some(store) = &self.store {
+            store.save_state(&state);
+        }
+        if let Err(err) = sender.try_send(BackendMsg::Ready(self.ready, state)) {
+            eprintln!("Failed to send backend message: {:?}", err);
+        }
+    }
+}
+
+fn backend_sender(ctx: &Context) -> Sender<BackendMsg> {
+    let context = ctx.data.read();
+    context
+        .get::<SenderKey>()
+        .expect("Expected Sender")
+        .0
+        .clone()
+}
+
+/// Fetches the channels and members the gateway left out of a partial guild, falling back
+/// to empty lists if the requests fail
+fn fetch_partial_guild(http: &Http, guild: &PartialGuild) -> GuildState {
+    let channels = guild.channels(http).unwrap_or_else(|err| {
+        eprintln!("Failed to fetch channels of {}: {:?}", guild.name, err);
+        HashMap::new()
+    });
+    let members = guild.members(http, Some(1000), None).unwrap_or_else(|err| {
+        eprintln!("Failed to fetch members of {}: {:?}", guild.name, err);
+        Vec::new()
+    });
+
+    GuildState::from_partial(guild, members, channels)
+}
 
 impl EventHandler for Handler {
     fn cache_ready(&self, ctx: Context, guilds: Vec<GuildId>) {
@@ -154,6 +270,23 @@ impl EventHandler for Handler {
             .expect("Failed to send backend message");
     }
     fn guild_create(&self, ctx: Context, guild: Guild, is_new: bool) {
+        {
+            let mut startup = self.startup.lock();
+            if let Some(state) = startup.as_mut() {
+                if state.pending.remove(&guild.id) {
+                    state.guilds.push(GuildState::from(&guild));
+
+                    if state.pending.is_empty() {
+                        if let Some(state) = startup.take() {
+                            state.finish(backend_sender(&ctx));
+                        }
+                    }
+
+                    return;
+                }
+            }
+        }
+
         let mut sender = {
             let context = ctx.data.read();
             context.get::<SenderKey>().expect("Expected Sender").clone()
@@ -331,6 +464,10 @@ impl EventHandler for Handler {
             .expect("Failed to send backend message");
     }
     fn message(&self, ctx: Context, new_message: Message) {
+        if let Some(store) = &self.store {
+            store.save_message(&new_message);
+        }
+
         let mut sender = {
             let context = ctx.data.read();
             context.get::<SenderKey>().expect("Expected Sender").clone()
@@ -342,6 +479,10 @@ impl EventHandler for Handler {
             .expect("Failed to send backend message");
     }
     fn message_delete(&self, ctx: Context, channel_id: ChannelId, message_id: MessageId) {
+        if let Some(store) = &self.store {
+            store.remove_message(channel_id, message_id);
+        }
+
         let mut sender = {
             let context = ctx.data.read();
             context.get::<SenderKey>().expect("Expected Sender").clone()
@@ -358,6 +499,12 @@ impl EventHandler for Handler {
         channel_id: ChannelId,
         deleted_messages: Vec<MessageId>,
     ) {
+        if let Some(store) = &self.store {
+            for message_id in deleted_messages.iter() {
+                store.remove_message(channel_id, *message_id);
+            }
+        }
+
         let mut sender = {
             let context = ctx.data.read();
             context.get::<SenderKey>().expect("Expected Sender").clone()
@@ -375,6 +522,10 @@ impl EventHandler for Handler {
         new: Option<Message>,
         event: MessageUpdateEvent,
     ) {
+        if let Some(store) = &self.store {
+            store.update_message(&event);
+        }
+
         let mut sender = {
             let context = ctx.data.read();
             context.get::<SenderKey>().expect("Expected Sender").clone()
@@ -442,34 +593,80 @@ impl EventHandler for Handler {
     }
 
     fn ready(&self, ctx: Context, data: Ready) {
-        let mut sender = {
-            let context = ctx.data.read();
-            context.get::<SenderKey>().expect("Expected Sender").clone()
-        };
-
-        let user = ctx.http.get_current_user().unwrap();
-        let guilds = ctx
-            .http
-            .get_guilds(&serenity::http::GuildPagination::After(GuildId(0)), 100)
-            .unwrap();
-        let http = &ctx.http;
-        let guilds = guilds
-            .into_iter()
-            .map(|g| g.id.to_partial_guild(http).unwrap())
-            .map(|pg| {
-                let channels = pg.channels(http).unwrap();
-                let members = pg.members(http, Some(1000), None).unwrap();
-                (pg, members, channels)
-            })
-            .collect::<Vec<_>>();
-
-        Arc::make_mut(&mut sender)
-            .0
-            .try_send(BackendMsg::Ready(
-                data,
-                crate::ui::InitializationState { guilds, user },
-            ))
-            .expect("Failed to send backend message");
+        // New sessions start out online, so the presence we chose is set again
+        if let Some(presence) = self.store.as_ref().and_then(|store| store.presence()) {
+            ctx.shard.set_presence(presence.activity(), presence.status);
+        }
+
+        let mut guilds = Vec::with_capacity(data.guilds.len());
+        let mut pending = HashSet::new();
+        for status in data.guilds.iter() {
+            match status {
+                GuildStatus::OnlineGuild(guild) => guilds.push(GuildState::from(guild)),
+                GuildStatus::OnlinePartialGuild(guild) => {
+                    guilds.push(fetch_partial_guild(&ctx.http, guild))
+                }
+                status => {
+                    pending.insert(status.id());
+                }
+            }
+        }
+
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let startup = Startup {
+            generation,
+            ready: data,
+            guilds,
+            pending,
+            store: self.store.clone(),
+        };
+        if startup.pending.is_empty() {
+            startup.finish(backend_sender(&ctx));
+            return;
+        }
+        *self.startup.lock() = Some(startup);
+
+        // Guilds that never show up over the gateway are fetched over REST so that
+        // startup can't hang on them
+        let pending_startup = Arc::clone(&self.startup);
+        let http = Arc::clone(&ctx.http);
+        let sender = backend_sender(&ctx);
+        let spawned = thread::Builder::new()
+            .name("Startup Timeout".to_string())
+            .spawn(move || {
+                thread::sleep(GUILD_CREATE_TIMEOUT);
+
+                // A newer session's startup is left alone, it has a timeout of its own
+                let startup = {
+                    let mut pending_startup = pending_startup.lock();
+                    let current = pending_startup.as_ref().map(|startup| startup.generation);
+                    if current == Some(generation) {
+                        pending_startup.take()
+                    } else {
+                        None
+                    }
+                };
+                let mut startup = match startup {
+                    Some(startup) => startup,
+                    None => return,
+                };
+
+                for guild_id in startup.pending.drain().collect::<Vec<_>>() {
+                    match guild_id.to_partial_guild(&http) {
+                        Ok(guild) => startup.guilds.push(fetch_partial_guild(&http, &guild)),
+                        Err(err) => eprintln!("Failed to fetch guild {}: {:?}", guild_id, err),
+                    }
+                }
+
+                startup.finish(sender);
+            });
+
+        if let Err(err) = spawned {
+            eprintln!("Failed to spawn startup timeout thread: {:?}", err);
+            if let Some(startup) = self.startup.lock().take() {
+                startup.finish(backend_sender(&ctx));
+            }
+        }
     }
 
     fn resume(&self, ctx: Context, data: ResumedEvent) {
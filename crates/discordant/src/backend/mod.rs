@@ -1,23 +1,78 @@
 mod backend_message;
 mod cache;
+mod credentials;
+mod disk_cache;
 mod event_handler;
+mod store;
+mod upload;
 
 pub use backend_message::BackendMsg;
+pub use cache::{Cache, ChannelData, ChannelKind, EmojiData, GuildData, PresenceData};
+pub use credentials::{forget_accounts, load_accounts, save_accounts, Account, TEMPORARY_ID};
+pub use store::{NotifyLevel, OwnPresence};
+pub use upload::{UploadFile, UploadState};
 
-use futures::channel::mpsc::{self, Receiver, Sender};
-use serenity::prelude::Mutex;
-use std::{sync::Arc, thread};
-use crate::ui;
+use crate::ui::images::{self, DecodedImage};
+use disk_cache::DiskCache;
+use futures::channel::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use serenity::{
+    model::{
+        channel::ReactionType,
+        id::{ChannelId, MessageId},
+    },
+    prelude::{Mutex, RwLock},
+};
+use std::{path::PathBuf, sync::Arc, thread};
+use store::Store;
 
-pub fn main(token: impl AsRef<str>) -> (
+/// Checks that a token looks like one and that Discord accepts it, giving whose it is
+pub fn check_token(token: &str) -> Result<serenity::model::user::CurrentUser, String> {
+    // Tokens of accounts with two factor authentication are shaped differently
+    if !token.starts_with("mfa.") {
+        serenity::client::validate_token(token)
+            .map_err(|_| "That doesn't look like a token".to_string())?;
+    }
+
+    serenity::http::raw::Http::new_with_token(token)
+        .get_current_user()
+        .map_err(|err| format!("Discord didn't accept the token: {}", err))
+}
+
+/// Whether Discord turns down a token it accepted before, as it does once the token is
+/// revoked. Not reaching Discord doesn't count, the stored account is still shown then
+pub fn token_revoked(token: &str) -> bool {
+    match serenity::http::raw::Http::new_with_token(token).get_current_user() {
+        Err(serenity::Error::Http(err)) => match *err {
+            serenity::http::HttpError::UnsuccessfulRequest(ref response) => {
+                response.status_code == serenity::http::StatusCode::UNAUTHORIZED
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Wipes everything stored about an account, for those which can't be connected to
+/// anymore to log out of
+pub fn clear_store(account_id: u64) {
+    if let Some(store) = Store::open(account_id) {
+        store.clear();
+    }
+}
+
+pub fn main(
+    token: impl AsRef<str>,
+    account_id: u64,
+) -> (
     Discord,
     Receiver<BackendMsg>,
-    Sender<String>,
-    Receiver<ui::DecodedImageData>,
+    UnboundedSender<ImageRequest>,
+    Receiver<(u64, Option<DecodedImage>)>,
 ) {
-    let (discord, backend_recv) = Discord::spawn(token);
+    let (download_sender, download_recv) = mpsc::unbounded();
+    let (discord, backend_recv) = Discord::spawn(token, account_id, download_sender);
 
-    let (url_sender, url_recv) = mpsc::channel(100);
+    let (url_sender, url_recv) = mpsc::unbounded();
     let (file_sender, file_recv) = mpsc::channel(100);
 
     std::thread::spawn(|| {
@@ -26,66 +81,174 @@ pub fn main(token: impl AsRef<str>) -> (
             .build()
             .unwrap();
 
-        runtime.block_on(async_main(url_recv, file_sender));
+        runtime.block_on(async_main(url_recv, file_sender, download_recv));
     });
 
     (discord, backend_recv, url_sender, file_recv)
 }
 
-async fn async_main(mut url_recv: Receiver<String>, file_sender: Sender<ui::DecodedImageData>) {
-    use futures::{
-        sink::SinkExt,
-        stream::{StreamExt, TryStreamExt},
-    };
-    use hyper::{client::Client, Uri};
-    use std::str::FromStr;
+/// A request for the image pipeline, the decoded image is sent back with the same id
+#[derive(Debug)]
+pub struct ImageRequest {
+    pub id: u64,
+    pub url: String,
+}
+
+/// A request to save the file at the url to the path, answered with a
+/// `BackendMsg::DownloadFinished` on the channel of the account which asked
+#[derive(Debug)]
+pub struct DownloadRequest {
+    pub url: String,
+    pub path: PathBuf,
+    pub reply: Sender<BackendMsg>,
+}
+
+type HttpsClient = hyper::client::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>;
+
+async fn async_main(
+    mut url_recv: UnboundedReceiver<ImageRequest>,
+    file_sender: Sender<(u64, Option<DecodedImage>)>,
+    download_recv: UnboundedReceiver<DownloadRequest>,
+) {
+    use futures::{sink::SinkExt, stream::StreamExt};
+    use hyper::client::Client;
 
     let client = {
         let https = hyper_tls::HttpsConnector::new().expect("Failed to create HTTPS connector");
         Client::builder().build::<_, hyper::Body>(https)
     };
 
-    while let Some(url) = url_recv.next().await {
-        let (client, mut file_sender) = (client.clone(), file_sender.clone());
-        let url = Uri::from_str(&url).expect("Failed to parse URL");
+    tokio::spawn(download_files(client.clone(), download_recv));
+
+    let disk_cache = DiskCache::open().map(Arc::new);
+
+    while let Some(ImageRequest { id, url }) = url_recv.next().await {
+        let (client, mut file_sender, disk_cache) =
+            (client.clone(), file_sender.clone(), disk_cache.clone());
 
         tokio::spawn(async move {
-            let file = match client.get(url).await {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("Http Error: {:?}", err);
-                    return;
-                }
+            let decoded = fetch_image(&client, disk_cache.as_ref().map(Arc::as_ref), &url).await;
+
+            // Always answer, even if the image failed, so the UI can forget the request
+            if let Err(err) = file_sender.send((id, decoded)).await {
+                eprintln!("File Send Error: {:?}", err);
             }
-            .into_body()
-            .try_concat()
-            .await;
+        });
+    }
+}
 
-            if let Some(decoded) = ui::decode_webp(&file.unwrap()) {
-                if let Err(err) = file_sender.send(decoded).await {
-                    eprintln!("File Send Error: {:?}", err);
-                }
+/// Saves files to disk as they're requested, without going through the image cache
+async fn download_files(
+    client: HttpsClient,
+    mut download_recv: UnboundedReceiver<DownloadRequest>,
+) {
+    use futures::{sink::SinkExt, stream::StreamExt};
+
+    while let Some(DownloadRequest {
+        url,
+        path,
+        mut reply,
+    }) = download_recv.next().await
+    {
+        let client = client.clone();
+
+        tokio::spawn(async move {
+            let result = match fetch(&client, &url).await {
+                Some(file) => std::fs::write(&path, &file).map_err(|err| err.to_string()),
+                None => Err(format!("Failed to fetch {}", url)),
+            };
+
+            if let Err(err) = reply.send(BackendMsg::DownloadFinished(path, result)).await {
+                eprintln!("Download Send Error: {:?}", err);
             }
         });
     }
 }
 
+/// Fetches and decodes an image, going through the on-disk cache first. Only images which
+/// decode are cached, so whatever else a server answers with is fetched again next time
+async fn fetch_image(
+    client: &HttpsClient,
+    disk_cache: Option<&DiskCache>,
+    url: &str,
+) -> Option<DecodedImage> {
+    if let Some(file) = disk_cache.and_then(|disk_cache| disk_cache.read(url)) {
+        return images::decode(&file);
+    }
+
+    // Unsuccessful responses come back as None, so their bodies never get here
+    let file = fetch(client, url).await?;
+    let decoded = images::decode(&file)?;
+    if let Some(disk_cache) = disk_cache {
+        disk_cache.write(url, &file);
+    }
+
+    Some(decoded)
+}
+
+async fn fetch(client: &HttpsClient, url: &str) -> Option<Vec<u8>> {
+    use futures::stream::TryStreamExt;
+    use hyper::Uri;
+    use std::str::FromStr;
+
+    let uri = match Uri::from_str(url) {
+        Ok(uri) => uri,
+        Err(err) => {
+            eprintln!("URL Parse Error: {:?}", err);
+            return None;
+        }
+    };
+
+    let response = match client.get(uri).await {
+        Ok(response) => response,
+        Err(err) => {
+            eprintln!("Http Error: {:?}", err);
+            return None;
+        }
+    };
+
+    if !response.status().is_success() {
+        eprintln!("Http Error: {} for {}", response.status(), url);
+        return None;
+    }
+
+    match response.into_body().try_concat().await {
+        Ok(file) => Some(file.to_vec()),
+        Err(err) => {
+            eprintln!("Http Error: {:?}", err);
+            None
+        }
+    }
+}
+
 pub struct Discord {
     http: Arc<serenity::http::raw::Http>,
     shard_manager: Arc<Mutex<serenity::client::bridge::gateway::ShardManager>>,
     voice_manager: Arc<Mutex<serenity::client::bridge::voice::ClientVoiceManager>>,
+    sender: Sender<BackendMsg>,
+    downloads: UnboundedSender<DownloadRequest>,
+    store: Option<Arc<Store>>,
 }
 
 impl Discord {
-    pub fn spawn(token: impl AsRef<str>) -> (Self, Receiver<BackendMsg>) {
-        let mut client = serenity::client::Client::new(token, event_handler::Handler)
-            .expect("Err creating client");
+    pub fn spawn(
+        token: impl AsRef<str>,
+        account_id: u64,
+        downloads: UnboundedSender<DownloadRequest>,
+    ) -> (Self, Receiver<BackendMsg>) {
+        let store = Store::open(account_id).map(Arc::new);
+        let mut client =
+            serenity::client::Client::new(token, event_handler::Handler::new(store.clone()))
+                .expect("Err creating client");
 
         let (sender, receiver) = mpsc::channel::<BackendMsg>(100);
         let discord = Self {
             http: Arc::clone(&client.cache_and_http.http),
             shard_manager: Arc::clone(&client.shard_manager),
             voice_manager: Arc::clone(&client.voice_manager),
+            sender: sender.clone(),
+            downloads,
+            store,
         };
 
         {
@@ -106,9 +269,102 @@ impl Discord {
         (discord, receiver)
     }
 
-    #[inline]
-    pub fn add_group_member(&self, group_id: u64, user_id: u64) -> Result<(), serenity::Error> {
-        (*self.http).add_group_recipient(group_id, user_id)
+    /// Connects another account, sharing this one's downloads
+    pub fn spawn_another(
+        &self,
+        token: impl AsRef<str>,
+        account_id: u64,
+    ) -> (Self, Receiver<BackendMsg>) {
+        Self::spawn(token, account_id, self.downloads.clone())
+    }
+
+    /// Disconnects from the gateway for good
+    pub fn shut_down(&self) {
+        self.shard_manager.lock().shutdown_all();
+    }
+
+    /// Adds someone to a group DM, they show up once the gateway tells about them
+    pub fn add_group_member(&self, group_id: u64, user_id: u64) {
+        self.request(
+            "Add Member",
+            move |http| http.add_group_recipient(group_id, user_id),
+            |result| result.err().map(|err| failed("Failed to add member", &err)),
+        );
+    }
+
+    pub fn remove_group_member(&self, group_id: u64, user_id: u64) {
+        self.request(
+            "Remove Member",
+            move |http| http.remove_group_recipient(group_id, user_id),
+            |result| {
+                result
+                    .err()
+                    .map(|err| failed("Failed to remove member", &err))
+            },
+        );
+    }
+
+    /// Leaves a group DM, delivered as a `BackendMsg::GroupLeft` since the gateway
+    /// doesn't tell about it
+    pub fn leave_group(&self, group_id: u64) {
+        self.request(
+            "Leave Group",
+            move |http| http.leave_group(group_id),
+            move |result| {
+                Some(match result {
+                    Ok(_) => BackendMsg::GroupLeft(ChannelId(group_id)),
+                    Err(err) => failed("Failed to leave group", &err),
+                })
+            },
+        );
+    }
+
+    /// Opens the DM with a user, it's created unless there already is one. Delivered as
+    /// a `BackendMsg::DmOpened`
+    pub fn open_dm(&self, user_id: u64) {
+        self.request(
+            "Open DM",
+            move |http| {
+                http.create_private_channel(&serde_json::json!({ "recipient_id": user_id }))
+            },
+            |result| {
+                Some(match result {
+                    Ok(channel) => BackendMsg::DmOpened(Arc::new(RwLock::new(channel))),
+                    Err(err) => failed("Failed to start DM", &err),
+                })
+            },
+        );
+    }
+
+    /// Makes a request on a separate thread, delivering the message its result makes if
+    /// there is one
+    fn request<T>(
+        &self,
+        name: &str,
+        request: impl FnOnce(&serenity::http::raw::Http) -> Result<T, serenity::Error> + Send + 'static,
+        done: impl FnOnce(Result<T, serenity::Error>) -> Option<BackendMsg> + Send + 'static,
+    ) {
+        self.background(name, move |http, sender| {
+            if let Some(msg) = done(request(http)) {
+                deliver(sender, msg);
+            }
+        });
+    }
+
+    /// Runs a job on a separate thread, giving it the HTTP client and the sender to
+    /// deliver what comes of it with. Jobs delivering a single message go through
+    /// `request` instead
+    fn background(
+        &self,
+        name: &str,
+        job: impl FnOnce(&serenity::http::raw::Http, &mut Sender<BackendMsg>) + Send + 'static,
+    ) {
+        let (http, mut sender) = (Arc::clone(&self.http), self.sender.clone());
+
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || job(&http, &mut sender))
+            .expect("Failed to spawn request thread");
     }
 
     #[inline]
@@ -121,13 +377,293 @@ impl Discord {
         (*self.http).add_member_role(guild_id, user_id, role_id)
     }
 
-    #[inline]
-    pub fn send_message(
+    /// Sends a message, or edits one of ours, on a separate thread. How it went is
+    /// delivered as a `BackendMsg::MessageSent`
+    pub fn send_message(&self, channel_id: ChannelId, editing: Option<MessageId>, content: String) {
+        let map = serde_json::json!({ "content": content });
+        self.request(
+            "Send",
+            move |http| match editing {
+                Some(message_id) => http.edit_message(channel_id.0, message_id.0, &map),
+                None => http.send_message(channel_id.0, &map),
+            },
+            move |result| {
+                let result = result.map(|_| ()).map_err(|err| err.to_string());
+                Some(BackendMsg::MessageSent(
+                    channel_id, editing, content, result,
+                ))
+            },
+        );
+    }
+
+    /// Adds or removes our reaction on a separate thread. It shows up once the gateway
+    /// echoes it back, failing is delivered as a `BackendMsg::RequestFailed`
+    pub fn react(
         &self,
-        channel_id: u64,
-        content: &str,
-    ) -> Result<serenity::model::channel::Message, serenity::Error> {
-        (*self.http).send_message(channel_id, &serde_json::json!({ "content": content }))
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: ReactionType,
+        add: bool,
+    ) {
+        self.request(
+            "React",
+            move |http| {
+                if add {
+                    http.create_reaction(channel_id.0, message_id.0, &emoji)
+                } else {
+                    http.delete_reaction(channel_id.0, message_id.0, None, &emoji)
+                }
+            },
+            |result| result.err().map(|err| failed("Failed to react", &err)),
+        );
+    }
+
+    /// Fetches who reacted to a message with an emoji on a separate thread, delivered as
+    /// a `BackendMsg::ReactionUsers`
+    pub fn load_reaction_users(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: ReactionType,
+    ) {
+        /// The most users the API returns at once
+        const USER_LIMIT: u8 = 100;
+
+        let reacted = emoji.clone();
+        self.request(
+            "Reactions",
+            move |http| {
+                http.get_reaction_users(channel_id.0, message_id.0, &reacted, USER_LIMIT, None)
+            },
+            move |result| match result {
+                Ok(users) => Some(BackendMsg::ReactionUsers(
+                    channel_id, message_id, emoji, users,
+                )),
+                Err(err) => {
+                    eprintln!("Reaction Fetch Error: {:?}", err);
+                    None
+                }
+            },
+        );
+    }
+
+    /// Fetches our DMs on a separate thread, delivered as a `BackendMsg::PrivateChannels`
+    pub fn load_dm_channels(&self) {
+        use serenity::model::channel::ChannelType;
+
+        self.request(
+            "DM Channels",
+            |http| http.get_user_dm_channels(),
+            |result| match result {
+                Ok(channels) => Some(BackendMsg::PrivateChannels(
+                    channels
+                        .into_iter()
+                        .filter(|channel| channel.kind == ChannelType::Private)
+                        .collect(),
+                )),
+                Err(err) => {
+                    eprintln!("DM Fetch Error: {:?}", err);
+                    None
+                }
+            },
+        );
+    }
+
+    /// Lets the channel know we're typing, on a separate thread
+    pub fn broadcast_typing(&self, channel_id: ChannelId) {
+        self.request(
+            "Typing",
+            move |http| http.broadcast_typing(channel_id.0),
+            |result| {
+                if let Err(err) = result {
+                    eprintln!("Typing Error: {:?}", err);
+                }
+                None
+            },
+        );
+    }
+
+    /// Uploads files on a separate thread, each as its own message like the official client
+    /// does, with the content sent along with the first one. The state of every file is
+    /// reported as a `BackendMsg::UploadProgress`
+    pub fn upload(
+        &self,
+        upload_id: u64,
+        channel_id: ChannelId,
+        content: Option<String>,
+        files: Vec<UploadFile>,
+    ) {
+        use serenity::http::AttachmentType;
+
+        self.background("Upload", move |http, sender| {
+            let mut content = content;
+
+            for (index, file) in files.iter().enumerate() {
+                let mut progress =
+                    |state| deliver(sender, BackendMsg::UploadProgress(upload_id, index, state));
+                progress(UploadState::Uploading);
+
+                let mut map = serde_json::Map::new();
+                if let Some(content) = &content {
+                    map.insert(
+                        "content".to_string(),
+                        serde_json::Value::String(content.clone()),
+                    );
+                }
+
+                let attachment = match file {
+                    UploadFile::Path(path) => AttachmentType::Path(path.as_path()),
+                    UploadFile::Bytes(name, bytes) => {
+                        AttachmentType::Bytes((bytes.as_slice(), name.as_str()))
+                    }
+                };
+                match http.send_files(channel_id.0, vec![attachment], map) {
+                    Ok(_) => {
+                        // The content went out with this file
+                        content = None;
+                        progress(UploadState::Sent);
+                    }
+                    Err(err) => {
+                        eprintln!("Upload Error: {:?}", err);
+                        progress(UploadState::Failed(err.to_string()));
+                    }
+                }
+            }
+        });
+    }
+
+    /// Saves the file at the url to the path in the background
+    pub fn download(&self, url: String, path: PathBuf) {
+        let reply = self.sender.clone();
+        if let Err(err) = self
+            .downloads
+            .unbounded_send(DownloadRequest { url, path, reply })
+        {
+            eprintln!("Download Request Error: {:?}", err);
+        }
+    }
+
+    /// The emoji picked most often, for the top of the emoji picker
+    pub fn frequent_emoji(&self) -> Vec<ReactionType> {
+        /// Enough for a few rows of the picker
+        const FREQUENT_LIMIT: usize = 24;
+
+        self.store
+            .as_ref()
+            .map(|store| store.frequent_emoji(FREQUENT_LIMIT))
+            .unwrap_or_default()
+    }
+
+    /// Counts an emoji as picked once more
+    pub fn count_emoji(&self, emoji: &ReactionType) {
+        if let Some(store) = &self.store {
+            store.count_emoji(emoji);
+        }
+    }
+
+    /// The newest message read in a channel, as far as this client knows
+    pub fn last_read(&self, channel_id: ChannelId) -> Option<MessageId> {
+        self.store
+            .as_ref()
+            .and_then(|store| store.last_read(channel_id))
+    }
+
+    /// Remembers a channel as read up to a message, so it isn't unread on the next launch
+    pub fn mark_read(&self, channel_id: ChannelId, message_id: MessageId) {
+        if let Some(store) = &self.store {
+            store.mark_read(channel_id, message_id);
+        }
+    }
+
+    /// The notification level set for a guild or channel, if one was
+    pub fn notify_level(&self, id: u64) -> Option<NotifyLevel> {
+        self.store.as_ref().and_then(|store| store.notify_level(id))
+    }
+
+    pub fn set_notify_level(&self, id: u64, level: Option<NotifyLevel>) {
+        if let Some(store) = &self.store {
+            store.set_notify_level(id, level);
+        }
+    }
+
+    /// Wipes everything stored about the account
+    pub fn log_out(&self) {
+        if let Some(store) = &self.store {
+            store.clear();
+        }
+    }
+
+    /// The presence we last chose, or being online with no activity
+    pub fn presence(&self) -> OwnPresence {
+        self.store
+            .as_ref()
+            .and_then(|store| store.presence())
+            .unwrap_or_default()
+    }
+
+    /// Shows others our status and activity, which are set again whenever we reconnect
+    pub fn set_presence(&self, presence: &OwnPresence) {
+        if let Some(store) = &self.store {
+            store.save_presence(presence);
+        }
+        if let Some(messenger) = self.messenger() {
+            messenger.set_presence(presence.activity(), presence.status);
+        }
+    }
+
+    /// The state the client started out with last time, if it was stored
+    pub fn stored_state(&self) -> Option<crate::ui::InitializationState> {
+        self.store.as_ref().and_then(|store| store.state())
+    }
+
+    /// Loads the latest messages of a channel on a separate thread, the stored history is
+    /// delivered first and then replaced by the fetched one, each as a
+    /// `BackendMsg::MessageHistory` with the oldest message first
+    pub fn load_messages(&self, channel_id: ChannelId) {
+        const HISTORY_LIMIT: u64 = 50;
+
+        let store = self.store.clone();
+        self.background("History", move |http, sender| {
+            if let Some(store) = &store {
+                let stored = store.messages(channel_id, HISTORY_LIMIT as usize);
+                if !stored.is_empty() {
+                    deliver(sender, BackendMsg::MessageHistory(channel_id, stored));
+                }
+            }
+
+            let query = format!("?limit={}", HISTORY_LIMIT);
+            match http.get_messages(channel_id.0, &query) {
+                Ok(mut messages) => {
+                    messages.reverse();
+
+                    if let Some(store) = &store {
+                        store.save_history(channel_id, &messages);
+                    }
+                    deliver(sender, BackendMsg::MessageHistory(channel_id, messages));
+                }
+                Err(err) => eprintln!("History Fetch Error: {:?}", err),
+            }
+        });
+    }
+
+    /// Asks the gateway for all members of a guild, large guilds only come with those
+    /// online. They arrive as `BackendMsg::GuildMembersOffline`
+    pub fn request_members(&self, guild_id: u64) {
+        use serenity::model::id::GuildId;
+
+        if let Some(messenger) = self.messenger() {
+            messenger.chunk_guilds(vec![GuildId(guild_id)], Some(0), Some(String::new()));
+        }
+    }
+
+    /// Sends commands to the gateway. The client only runs a single shard, which every
+    /// guild is on
+    fn messenger(&self) -> Option<serenity::client::bridge::gateway::ShardMessenger> {
+        let manager = self.shard_manager.lock();
+        let runners = manager.runners.lock();
+        runners.values().next().map(|runner| {
+            serenity::client::bridge::gateway::ShardMessenger::new(runner.runner_tx.clone())
+        })
     }
 
     #[inline]
@@ -150,3 +686,14 @@ struct SenderKey;
 impl serenity::prelude::TypeMapKey for SenderKey {
     type Value = Arc<SendWrap>;
 }
+
+fn deliver(sender: &mut Sender<BackendMsg>, msg: BackendMsg) {
+    if let Err(err) = sender.try_send(msg) {
+        eprintln!("Backend Send Error: {:?}", err);
+    }
+}
+
+/// A `BackendMsg::RequestFailed` telling what failed and why
+fn failed(what: &str, err: &serenity::Error) -> BackendMsg {
+    BackendMsg::RequestFailed(format!("{}: {}", what, err))
+}
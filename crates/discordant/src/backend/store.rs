@@ -0,0 +1,459 @@
+use crate::ui::InitializationState;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    model::{
+        channel::{Message, ReactionType},
+        event::MessageUpdateEvent,
+        gateway::Activity,
+        id::{ChannelId, MessageId},
+        user::OnlineStatus,
+    },
+    prelude::Mutex,
+};
+use std::collections::{HashMap, HashSet};
+
+/// How many of the newest messages of each channel are kept around
+const MESSAGES_PER_CHANNEL: usize = 500;
+/// How far a channel may go over `MESSAGES_PER_CHANNEL` before its oldest messages are
+/// dropped, so they aren't dropped one at a time with every new message
+const TRIM_SLACK: usize = 50;
+const STATE_KEY: &[u8] = b"state";
+const PRESENCE_KEY: &[u8] = b"presence";
+
+/// Which messages of a guild or channel show a desktop notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NotifyLevel {
+    All,
+    Mentions,
+    Nothing,
+}
+
+/// The status and activity we chose to show others
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnPresence {
+    pub status: OnlineStatus,
+    /// Shown as what we're playing
+    pub activity: Option<String>,
+}
+
+impl Default for OwnPresence {
+    fn default() -> Self {
+        Self {
+            status: OnlineStatus::Online,
+            activity: None,
+        }
+    }
+}
+
+impl OwnPresence {
+    pub fn activity(&self) -> Option<Activity> {
+        self.activity.as_ref().map(|name| Activity::playing(name))
+    }
+}
+
+/// Guilds, channels, users and messages persisted under the user's data directory, so
+/// that history shows up instantly on launch and while offline
+pub struct Store {
+    messages: sled::Tree,
+    state: sled::Tree,
+    /// How often each emoji was picked, keyed by the encoded emoji
+    emoji: sled::Tree,
+    /// The newest message read in each channel, keyed by the channel's id
+    read: sled::Tree,
+    /// The notification levels set for guilds and channels, keyed by their id
+    notify: sled::Tree,
+    /// How many messages are stored for each channel, counted once a channel's messages
+    /// first change in this run
+    message_counts: Mutex<HashMap<ChannelId, usize>>,
+}
+
+impl Store {
+    /// Opens the store of an account, logging why if it can't be opened so the client can
+    /// carry on without one. The first account keeps the store from before there were
+    /// several, and the one logged in to with `DISCORD_TOKEN` has its own
+    pub fn open(account_id: u64) -> Option<Self> {
+        let name = match account_id {
+            0 => "store".to_string(),
+            super::TEMPORARY_ID => "store-env".to_string(),
+            _ => format!("store-{}", account_id),
+        };
+        let path = match dirs::data_dir() {
+            Some(dir) => dir.join("discordant").join(name),
+            None => {
+                eprintln!("Store Error: No data directory to keep the store in");
+                return None;
+            }
+        };
+
+        let opened = sled::Db::open(&path).and_then(|db| {
+            Ok(Self {
+                messages: db.open_tree("messages")?,
+                state: db.open_tree("state")?,
+                emoji: db.open_tree("emoji")?,
+                read: db.open_tree("read")?,
+                notify: db.open_tree("notify")?,
+                message_counts: Mutex::new(HashMap::new()),
+            })
+        });
+
+        match opened {
+            Ok(store) => Some(store),
+            Err(err) => {
+                eprintln!("Store Error: Failed to open {}: {:?}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// The state the client last started out with
+    pub fn state(&self) -> Option<InitializationState> {
+        match self.state.get(STATE_KEY) {
+            Ok(Some(state)) => decode(&state),
+            Ok(None) => None,
+            Err(err) => {
+                eprintln!("Store Error: {:?}", err);
+                None
+            }
+        }
+    }
+
+    pub fn save_state(&self, state: &InitializationState) {
+        if let Some(encoded) = encode(state) {
+            log(self.state.insert(STATE_KEY, encoded));
+        }
+    }
+
+    /// The presence we last chose, if we ever did
+    pub fn presence(&self) -> Option<OwnPresence> {
+        match self.state.get(PRESENCE_KEY) {
+            Ok(presence) => presence.and_then(|presence| decode(&presence)),
+            Err(err) => {
+                eprintln!("Store Error: {:?}", err);
+                None
+            }
+        }
+    }
+
+    pub fn save_presence(&self, presence: &OwnPresence) {
+        if let Some(encoded) = encode(presence) {
+            log(self.state.insert(PRESENCE_KEY, encoded));
+        }
+    }
+
+    /// Wipes everything stored, it's of another account once we log in again
+    pub fn clear(&self) {
+        let trees = [
+            &self.messages,
+            &self.state,
+            &self.emoji,
+            &self.read,
+            &self.notify,
+        ];
+        for tree in trees.iter() {
+            let keys = tree
+                .iter()
+                .keys()
+                .filter_map(Result::ok)
+                .collect::<Vec<_>>();
+            for key in keys {
+                log(tree.remove(key));
+            }
+        }
+        self.message_counts.lock().clear();
+    }
+
+    /// The most often picked emoji, most often picked first
+    pub fn frequent_emoji(&self, limit: usize) -> Vec<ReactionType> {
+        let mut counted = self
+            .emoji
+            .iter()
+            .filter_map(|entry| match entry {
+                Ok((emoji, count)) => Some((decode::<ReactionType>(&emoji)?, u64_of(&count))),
+                Err(err) => {
+                    eprintln!("Store Error: {:?}", err);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        counted.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        counted
+            .into_iter()
+            .take(limit)
+            .map(|(emoji, _)| emoji)
+            .collect()
+    }
+
+    pub fn count_emoji(&self, emoji: &ReactionType) {
+        let key = match encode(emoji) {
+            Some(key) => key,
+            None => return,
+        };
+
+        let count = match self.emoji.get(&key) {
+            Ok(count) => count.map_or(0, |count| u64_of(&count)),
+            Err(err) => return eprintln!("Store Error: {:?}", err),
+        };
+        log(self.emoji.insert(key, (count + 1).to_be_bytes().to_vec()));
+    }
+
+    /// The newest message read in a channel, if it was ever read
+    pub fn last_read(&self, channel_id: ChannelId) -> Option<MessageId> {
+        match self.read.get(channel_id.0.to_be_bytes()) {
+            Ok(message_id) => message_id.map(|message_id| MessageId(u64_of(&message_id))),
+            Err(err) => {
+                eprintln!("Store Error: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Remembers a channel as read up to a message, unless a newer one was read already
+    pub fn mark_read(&self, channel_id: ChannelId, message_id: MessageId) {
+        if self
+            .last_read(channel_id)
+            .map_or(false, |read| read >= message_id)
+        {
+            return;
+        }
+
+        log(self.read.insert(
+            channel_id.0.to_be_bytes(),
+            message_id.0.to_be_bytes().to_vec(),
+        ));
+    }
+
+    /// The notification level set for a guild or channel, if one was
+    pub fn notify_level(&self, id: u64) -> Option<NotifyLevel> {
+        match self.notify.get(id.to_be_bytes()) {
+            Ok(level) => level.and_then(|level| decode(&level)),
+            Err(err) => {
+                eprintln!("Store Error: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Sets the notification level of a guild or channel, no level goes back to the
+    /// default one
+    pub fn set_notify_level(&self, id: u64, level: Option<NotifyLevel>) {
+        match level.and_then(|level| encode(&level)) {
+            Some(encoded) => log(self.notify.insert(id.to_be_bytes(), encoded)),
+            None => log(self.notify.remove(id.to_be_bytes())),
+        }
+    }
+
+    /// The newest stored messages of a channel, oldest first
+    pub fn messages(&self, channel_id: ChannelId, limit: usize) -> Vec<Message> {
+        let mut messages = self
+            .messages
+            .scan_prefix(channel_id.0.to_be_bytes())
+            .values()
+            .rev()
+            .take(limit)
+            .filter_map(|message| match message {
+                Ok(message) => decode(&message),
+                Err(err) => {
+                    eprintln!("Store Error: {:?}", err);
+                    None
+                }
+            })
+            .collect::<Vec<Message>>();
+        messages.reverse();
+
+        messages
+    }
+
+    /// Stores a message which just arrived, dropping the channel's oldest once it has
+    /// too many
+    pub fn save_message(&self, message: &Message) {
+        if self.insert_message(message) {
+            self.messages_changed(message.channel_id, 1, 0);
+        }
+    }
+
+    /// Stores a freshly fetched page of history, oldest message first. Stored messages
+    /// within the span of the page which it doesn't contain were deleted in the meantime
+    pub fn save_history(&self, channel_id: ChannelId, messages: &[Message]) {
+        let (oldest, newest) = match (messages.first(), messages.last()) {
+            (Some(oldest), Some(newest)) => (oldest.id, newest.id),
+            _ => return,
+        };
+
+        let fetched = messages
+            .iter()
+            .map(|message| message_key(channel_id, message.id))
+            .collect::<HashSet<_>>();
+        let stale = self
+            .messages
+            .range(message_key(channel_id, oldest)..=message_key(channel_id, newest))
+            .keys()
+            .filter_map(Result::ok)
+            .filter(|key| !fetched.contains(key.as_ref()))
+            .collect::<Vec<_>>();
+        let removed = stale.len();
+        for key in stale {
+            log(self.messages.remove(key));
+        }
+
+        let added = messages
+            .iter()
+            .filter(|message| self.insert_message(message))
+            .count();
+        self.messages_changed(channel_id, added, removed);
+    }
+
+    pub fn update_message(&self, event: &MessageUpdateEvent) {
+        let key = message_key(event.channel_id, event.id);
+        let mut message = match self.messages.get(key) {
+            Ok(Some(message)) => match decode::<Message>(&message) {
+                Some(message) => message,
+                None => return,
+            },
+            Ok(None) => return,
+            Err(err) => return eprintln!("Store Error: {:?}", err),
+        };
+
+        if let Some(content) = &event.content {
+            message.content = content.clone();
+        }
+        if let Some(edited) = event.edited_timestamp {
+            message.edited_timestamp = Some(edited);
+        }
+        if let Some(embeds) = &event.embeds {
+            message.embeds = embeds
+                .iter()
+                .filter_map(|embed| serde_json::from_value(embed.clone()).ok())
+                .collect();
+        }
+        if let Some(attachments) = &event.attachments {
+            message.attachments = attachments.clone();
+        }
+        if let Some(pinned) = event.pinned {
+            message.pinned = pinned;
+        }
+        if let Some(mentions) = &event.mentions {
+            message.mentions = mentions.clone();
+        }
+        if let Some(mention_everyone) = event.mention_everyone {
+            message.mention_everyone = mention_everyone;
+        }
+        if let Some(mention_roles) = &event.mention_roles {
+            message.mention_roles = mention_roles.clone();
+        }
+
+        self.insert_message(&message);
+    }
+
+    pub fn remove_message(&self, channel_id: ChannelId, message_id: MessageId) {
+        match self.messages.remove(message_key(channel_id, message_id)) {
+            Ok(Some(_)) => self.messages_changed(channel_id, 0, 1),
+            Ok(None) => {}
+            Err(err) => eprintln!("Store Error: {:?}", err),
+        }
+    }
+
+    /// Stores a message, telling whether it wasn't stored before
+    fn insert_message(&self, message: &Message) -> bool {
+        let encoded = match encode(message) {
+            Some(encoded) => encoded,
+            None => return false,
+        };
+
+        match self
+            .messages
+            .insert(message_key(message.channel_id, message.id), encoded)
+        {
+            Ok(previous) => previous.is_none(),
+            Err(err) => {
+                eprintln!("Store Error: {:?}", err);
+                false
+            }
+        }
+    }
+
+    /// Keeps count of a channel's messages as they're added and removed, and drops the
+    /// oldest once the channel has too many
+    fn messages_changed(&self, channel_id: ChannelId, added: usize, removed: usize) {
+        let mut counts = self.message_counts.lock();
+        let count = match counts.get(&channel_id) {
+            Some(count) => (count + added).saturating_sub(removed),
+            // Counting them now takes in the change already
+            None => self.messages_of(channel_id).count(),
+        };
+
+        let count = if count > MESSAGES_PER_CHANNEL + TRIM_SLACK {
+            self.trim_messages(channel_id);
+            MESSAGES_PER_CHANNEL
+        } else {
+            count
+        };
+        counts.insert(channel_id, count);
+    }
+
+    /// The keys of a channel's stored messages, oldest first
+    fn messages_of(
+        &self,
+        channel_id: ChannelId,
+    ) -> impl DoubleEndedIterator<Item = sled::Result<sled::IVec>> + '_ {
+        self.messages.scan_prefix(channel_id.0.to_be_bytes()).keys()
+    }
+
+    /// Drops the oldest messages of a channel beyond the newest `MESSAGES_PER_CHANNEL`
+    fn trim_messages(&self, channel_id: ChannelId) {
+        let overflow = self
+            .messages_of(channel_id)
+            .rev()
+            .skip(MESSAGES_PER_CHANNEL)
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        for key in overflow {
+            log(self.messages.remove(key));
+        }
+    }
+}
+
+/// Messages are keyed by their channel and then their id, so a channel's messages are
+/// stored next to each other and oldest first
+fn message_key(channel_id: ChannelId, message_id: MessageId) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&channel_id.0.to_be_bytes());
+    key[8..].copy_from_slice(&message_id.0.to_be_bytes());
+
+    key
+}
+
+fn u64_of(bytes: &[u8]) -> u64 {
+    let mut count = [0; 8];
+    if bytes.len() == count.len() {
+        count.copy_from_slice(bytes);
+    }
+
+    u64::from_be_bytes(count)
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Option<Vec<u8>> {
+    match serde_json::to_vec(value) {
+        Ok(encoded) => Some(encoded),
+        Err(err) => {
+            eprintln!("Store Encode Error: {:?}", err);
+            None
+        }
+    }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    match serde_json::from_slice(bytes) {
+        Ok(decoded) => Some(decoded),
+        Err(err) => {
+            eprintln!("Store Decode Error: {:?}", err);
+            None
+        }
+    }
+}
+
+fn log<T>(result: sled::Result<T>) {
+    if let Err(err) = result {
+        eprintln!("Store Error: {:?}", err);
+    }
+}
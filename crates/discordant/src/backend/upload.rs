@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+/// A file waiting to be uploaded, either on disk or only in memory like pasted images
+#[derive(Debug, Clone)]
+pub enum UploadFile {
+    Path(PathBuf),
+    Bytes(String, Vec<u8>),
+}
+
+impl UploadFile {
+    pub fn name(&self) -> String {
+        match self {
+            UploadFile::Path(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            UploadFile::Bytes(name, _) => name.clone(),
+        }
+    }
+
+    /// The size of the file in bytes, if it can be determined
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            UploadFile::Path(path) => std::fs::metadata(path).ok().map(|meta| meta.len()),
+            UploadFile::Bytes(_, bytes) => Some(bytes.len() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UploadState {
+    Uploading,
+    Sent,
+    Failed(String),
+}
@@ -6,9 +6,16 @@ mod ui;
 use relm::Widget;
 
 fn main() {
-    dotenv::dotenv().unwrap();
+    // Only needed to set DISCORD_TOKEN while developing
+    dotenv::dotenv().ok();
+    gtk::init().expect("Failed to initialize GTK");
 
-    ui::Win::run(()).unwrap();
+    let accounts = ui::login::accounts();
+    if accounts.is_empty() {
+        return;
+    }
+
+    ui::Win::run(accounts).unwrap();
 }
 
 // fn main() {
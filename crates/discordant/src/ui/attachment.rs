@@ -0,0 +1,117 @@
+use super::{embed, images::ImageLoader, Msg};
+use gtk::{BoxExt, ButtonExt, ContainerExt, LabelExt, Orientation, StyleContextExt, WidgetExt};
+use relm::EventStream;
+use serenity::model::channel::Attachment;
+
+const IMAGE_MAX_WIDTH: f64 = 400.0;
+const IMAGE_MAX_HEIGHT: f64 = 300.0;
+/// Files with these extensions are previewed, as long as Discord knows their size
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// A file attached to a message, shown as a card with its name and size and a button to
+/// save it. Images are previewed above the card and open in a viewer when clicked
+pub struct AttachmentView {
+    container: gtk::Box,
+}
+
+impl AttachmentView {
+    pub fn new(
+        attachment: &Attachment,
+        images: &mut ImageLoader,
+        stream: &EventStream<Msg>,
+    ) -> Self {
+        let container = gtk::Box::new(Orientation::Vertical, 4);
+
+        let dimensions = attachment.dimensions().filter(|_| is_image(attachment));
+        if let Some((width, height)) = dimensions {
+            let preview = embed::picture(width, height, IMAGE_MAX_WIDTH, IMAGE_MAX_HEIGHT);
+            images.load(attachment.proxy_url.clone(), &preview);
+
+            // Drawing areas don't receive clicks on their own
+            let event_box = gtk::EventBox::new();
+            event_box.set_halign(gtk::Align::Start);
+            event_box.add(preview.widget());
+
+            let (stream, viewed) = (stream.clone(), attachment.clone());
+            event_box.connect_button_press_event(move |_, _| {
+                stream.emit(Msg::ViewImage(viewed.clone()));
+
+                gtk::Inhibit(true)
+            });
+            container.pack_start(&event_box, false, false, 0);
+        }
+
+        let card = gtk::Box::new(Orientation::Horizontal, 6);
+        card.set_halign(gtk::Align::Start);
+        card.get_style_context().add_class("frame");
+
+        let icon_name = if dimensions.is_some() {
+            "image-x-generic"
+        } else {
+            "text-x-generic"
+        };
+        let icon = gtk::Image::new_from_icon_name(Some(icon_name), gtk::IconSize::Dnd);
+        card.pack_start(&icon, false, false, 0);
+
+        let details = gtk::Box::new(Orientation::Vertical, 0);
+        let name = gtk::Label::new(Some(&attachment.filename));
+        name.set_xalign(0.0);
+        name.set_ellipsize(pango::EllipsizeMode::Middle);
+        name.set_max_width_chars(40);
+        let size = gtk::Label::new(Some(&format_size(attachment.size)));
+        size.set_xalign(0.0);
+        size.get_style_context().add_class("dim-label");
+        details.pack_start(&name, false, false, 0);
+        details.pack_start(&size, false, false, 0);
+        card.pack_start(&details, true, true, 0);
+
+        let download =
+            gtk::Button::new_from_icon_name(Some("document-save-symbolic"), gtk::IconSize::Button);
+        download.set_relief(gtk::ReliefStyle::None);
+        download.set_tooltip_text(Some("Download"));
+        download.set_valign(gtk::Align::Center);
+        let (stream, downloaded) = (stream.clone(), attachment.clone());
+        download.connect_clicked(move |_| {
+            stream.emit(Msg::DownloadAttachment(downloaded.clone()));
+        });
+        card.pack_start(&download, false, false, 0);
+
+        container.pack_start(&card, false, false, 0);
+        container.show_all();
+
+        Self { container }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+fn is_image(attachment: &Attachment) -> bool {
+    std::path::Path::new(&attachment.filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image| extension.eq_ignore_ascii_case(image))
+        })
+}
+
+/// A file size in the largest unit it's at least one of
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+
+    let mut scaled = size as f64;
+    let mut unit = 0;
+    while scaled >= 1024.0 && unit < UNITS.len() - 1 {
+        scaled /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", scaled, UNITS[unit])
+    }
+}
@@ -0,0 +1,183 @@
+use super::unread::{Unread, UnreadLabel};
+use crate::backend::{ChannelData, ChannelKind};
+use gtk::{
+    ContainerExt, IsA, LabelExt, ListBoxExt, ListBoxRowExt, ScrolledWindowExt, StyleContextExt,
+    WidgetExt,
+};
+use serenity::model::id::ChannelId;
+use std::collections::HashMap;
+
+/// The channels of the currently selected guild, grouped under their categories
+pub struct ChannelList {
+    container: gtk::ScrolledWindow,
+    list: gtk::ListBox,
+    rows: Vec<Option<ChannelId>>,
+    labels: HashMap<ChannelId, UnreadLabel>,
+}
+
+impl ChannelList {
+    pub fn new() -> Self {
+        let list = gtk::ListBox::new();
+
+        let container = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        container.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        container.add(&list);
+
+        Self {
+            container,
+            list,
+            rows: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::ScrolledWindow {
+        &self.container
+    }
+
+    pub fn list(&self) -> &gtk::ListBox {
+        &self.list
+    }
+
+    /// The text channel displayed at a row, headers and voice channels have none
+    pub fn channel_at(&self, row: usize) -> Option<ChannelId> {
+        self.rows
+            .get(row)
+            .cloned()
+            .and_then(|channel_id| channel_id)
+    }
+
+    /// Replaces the listed channels, keeping the given channel selected if it's still
+    /// listed and selecting the first text channel otherwise
+    pub fn set_channels(
+        &mut self,
+        channels: &HashMap<u64, ChannelData>,
+        selected: Option<ChannelId>,
+        unread: &HashMap<ChannelId, Unread>,
+    ) {
+        for child in self.list.get_children() {
+            self.list.remove(&child);
+        }
+        self.rows.clear();
+        self.labels.clear();
+
+        for (category, channels) in sorted_channels(channels) {
+            if let Some((_, category)) = category {
+                let header = gtk::Label::new(None);
+                header.set_markup(&format!(
+                    "<b>{}</b>",
+                    glib::markup_escape_text(&category.name.to_uppercase())
+                ));
+                header.set_xalign(0.0);
+                header.get_style_context().add_class("dim-label");
+
+                self.push_row(&header, None);
+            }
+
+            for (channel_id, channel) in channels {
+                let (prefix, channel_id) = match channel.kind {
+                    ChannelKind::Text | ChannelKind::News => ("#", Some(ChannelId(channel_id))),
+                    ChannelKind::Voice => ("\u{1f50a}", None),
+                    _ => ("#", None),
+                };
+
+                let text = format!("{} {}", prefix, channel.name);
+                match channel_id {
+                    Some(channel_id) => {
+                        let label = UnreadLabel::new(&text);
+                        label.set(unread.get(&channel_id).cloned().unwrap_or_default());
+                        self.push_row(label.widget(), Some(channel_id));
+                        self.labels.insert(channel_id, label);
+                    }
+                    None => {
+                        let label = gtk::Label::new(Some(&text));
+                        label.set_xalign(0.0);
+                        label.get_style_context().add_class("dim-label");
+                        self.push_row(&label, None);
+                    }
+                }
+            }
+        }
+
+        self.list.show_all();
+
+        let selected = self
+            .rows
+            .iter()
+            .position(|channel_id| selected.is_some() && *channel_id == selected)
+            .or_else(|| self.rows.iter().position(Option::is_some));
+        if let Some(row) = selected.and_then(|row| self.list.get_row_at_index(row as i32)) {
+            self.list.select_row(Some(&row));
+        }
+    }
+
+    /// Shows what's unread in a channel, if it's listed
+    pub fn set_unread(&self, channel_id: ChannelId, unread: Unread) {
+        if let Some(label) = self.labels.get(&channel_id) {
+            label.set(unread);
+        }
+    }
+
+    fn push_row<W: IsA<gtk::Widget>>(&mut self, child: &W, channel_id: Option<ChannelId>) {
+        let row = gtk::ListBoxRow::new();
+        row.add(child);
+        row.set_selectable(channel_id.is_some());
+        row.set_activatable(channel_id.is_some());
+
+        self.list.add(&row);
+        self.rows.push(channel_id);
+    }
+}
+
+/// Orders channels the way the official client does, uncategorized channels come first
+/// and categories follow by position, with text channels above voice channels in each
+fn sorted_channels(
+    channels: &HashMap<u64, ChannelData>,
+) -> Vec<(Option<(u64, &ChannelData)>, Vec<(u64, &ChannelData)>)> {
+    let sort_key = |(channel_id, channel): &(u64, &ChannelData)| {
+        (
+            channel.kind == ChannelKind::Voice,
+            channel.position,
+            *channel_id,
+        )
+    };
+
+    let mut categories = channels
+        .iter()
+        .filter(|(_, channel)| channel.kind == ChannelKind::Category)
+        .map(|(category_id, category)| (Some((*category_id, category)), Vec::new()))
+        .collect::<Vec<_>>();
+    categories.sort_by_key(|(category, _)| category.map(|(id, c)| (c.position, id)));
+
+    let mut uncategorized = Vec::new();
+    for (channel_id, channel) in channels.iter() {
+        match channel.kind {
+            ChannelKind::Text | ChannelKind::Voice | ChannelKind::News | ChannelKind::Store => {}
+            _ => continue,
+        }
+
+        let category = channel.category_id.and_then(|category_id| {
+            categories
+                .iter_mut()
+                .find(|(category, _)| category.map(|(id, _)| id) == Some(category_id))
+        });
+
+        match category {
+            Some((_, children)) => children.push((*channel_id, channel)),
+            None => uncategorized.push((*channel_id, channel)),
+        }
+    }
+
+    uncategorized.sort_by_key(sort_key);
+    for (_, children) in categories.iter_mut() {
+        children.sort_by_key(sort_key);
+    }
+
+    let mut sorted = Vec::with_capacity(categories.len() + 1);
+    if !uncategorized.is_empty() {
+        sorted.push((None, uncategorized));
+    }
+    sorted.extend(categories);
+
+    sorted
+}
@@ -0,0 +1,592 @@
+use super::{
+    attachment::AttachmentView,
+    embed::EmbedView,
+    images::{Avatar, ImageLoader},
+    markdown, EmojiTarget, Msg,
+};
+use crate::backend::Cache;
+use gtk::{
+    AdjustmentExt, BinExt, BoxExt, ButtonExt, Cast, ContainerExt, FlowBoxExt, LabelExt, ListBoxExt,
+    ListBoxRowExt, Orientation, ScrolledWindowExt, StyleContextExt, WidgetExt,
+};
+use relm::EventStream;
+use serenity::model::{
+    channel::{Embed, Message, Reaction, ReactionType},
+    event::MessageUpdateEvent,
+    id::{ChannelId, MessageId, UserId},
+    user::User,
+};
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+const AVATAR_RADIUS: f64 = 25.0;
+const REACTION_EMOJI_SIZE: f64 = 16.0;
+
+/// The message timeline of the currently selected channel
+pub struct ChatView {
+    container: gtk::ScrolledWindow,
+    list: gtk::ListBox,
+    channel: Option<ChannelId>,
+    guild_id: Option<u64>,
+    rows: HashMap<MessageId, MessageRow>,
+    /// A message to scroll to as soon as it's displayed
+    jump: Option<MessageId>,
+    stream: EventStream<Msg>,
+}
+
+struct MessageRow {
+    row: gtk::ListBoxRow,
+    author_id: UserId,
+    text: String,
+    content: gtk::Box,
+    embeds: gtk::Box,
+    reaction_bar: gtk::FlowBox,
+    reactions: Vec<ReactionCount>,
+    timestamp: gtk::Label,
+    sent: String,
+}
+
+/// How many users reacted to a message with an emoji, and whether we're one of them
+struct ReactionCount {
+    emoji: ReactionType,
+    count: u64,
+    me: bool,
+}
+
+impl ChatView {
+    pub fn new(stream: EventStream<Msg>) -> Self {
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+
+        let container = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        container.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        container.add(&list);
+
+        // Keep the view pinned to the newest message unless the user scrolled up
+        if let Some(adjustment) = container.get_vadjustment() {
+            // Focusing a row scrolls it into view
+            list.set_focus_vadjustment(&adjustment);
+
+            let pinned = Rc::new(Cell::new(true));
+
+            let pinned_changed = Rc::clone(&pinned);
+            adjustment.connect_changed(move |adj| {
+                if pinned_changed.get() {
+                    adj.set_value(adj.get_upper() - adj.get_page_size());
+                }
+            });
+            adjustment.connect_value_changed(move |adj| {
+                pinned.set(adj.get_value() >= adj.get_upper() - adj.get_page_size() - 1.0);
+            });
+        }
+
+        Self {
+            container,
+            list,
+            channel: None,
+            guild_id: None,
+            rows: HashMap::new(),
+            jump: None,
+            stream,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::ScrolledWindow {
+        &self.container
+    }
+
+    pub fn channel(&self) -> Option<ChannelId> {
+        self.channel
+    }
+
+    /// Switches the view to another channel, dropping all currently displayed messages.
+    /// Mentions are resolved within the channel's guild
+    pub fn set_channel(&mut self, channel_id: ChannelId, guild_id: Option<u64>) {
+        for (_, message_row) in self.rows.drain() {
+            self.list.remove(&message_row.row);
+        }
+
+        self.channel = Some(channel_id);
+        self.guild_id = guild_id;
+        self.jump = None;
+    }
+
+    /// Drops all displayed messages, leaving no channel open
+    pub fn clear(&mut self) {
+        for (_, message_row) in self.rows.drain() {
+            self.list.remove(&message_row.row);
+        }
+
+        self.channel = None;
+        self.guild_id = None;
+        self.jump = None;
+    }
+
+    /// Scrolls to a message of the current channel, which may only be displayed once its
+    /// history arrives
+    pub fn jump_to(&mut self, message_id: MessageId) {
+        self.jump = Some(message_id);
+        self.try_jump();
+    }
+
+    /// Merges a channel's history into the view, expects the oldest message first.
+    /// Displayed messages within the span of the history that it doesn't contain were
+    /// deleted in the meantime
+    pub fn set_history(
+        &mut self,
+        channel_id: ChannelId,
+        messages: Vec<Message>,
+        cache: &Cache,
+        images: &mut ImageLoader,
+    ) {
+        if self.channel != Some(channel_id) {
+            return;
+        }
+
+        if let (Some(oldest), Some(newest)) = (messages.first(), messages.last()) {
+            let (oldest, newest) = (oldest.id, newest.id);
+            let deleted = self
+                .rows
+                .keys()
+                .filter(|message_id| **message_id >= oldest && **message_id <= newest)
+                .filter(|message_id| !messages.iter().any(|message| message.id == **message_id))
+                .cloned()
+                .collect::<Vec<_>>();
+            self.remove_bulk(channel_id, &deleted);
+        }
+
+        for message in messages.iter() {
+            if self.rows.contains_key(&message.id) {
+                continue;
+            }
+
+            // Rows are kept ordered by id, which is the order messages were sent in
+            let position = self
+                .rows
+                .keys()
+                .filter(|message_id| **message_id < message.id)
+                .count();
+            self.insert(message, position as i32, cache, images);
+        }
+
+        self.try_jump();
+    }
+
+    pub fn push(&mut self, message: &Message, cache: &Cache, images: &mut ImageLoader) {
+        if self.channel != Some(message.channel_id) || self.rows.contains_key(&message.id) {
+            return;
+        }
+
+        self.insert(message, -1, cache, images);
+        self.try_jump();
+    }
+
+    pub fn update(&mut self, event: &MessageUpdateEvent, cache: &Cache, images: &mut ImageLoader) {
+        if self.channel != Some(event.channel_id) {
+            return;
+        }
+
+        if let Some(message_row) = self.rows.get_mut(&event.id) {
+            if let Some(content) = &event.content {
+                markdown::render(
+                    &message_row.content,
+                    &markdown::parse(content),
+                    cache,
+                    self.guild_id,
+                    images,
+                );
+                message_row.text = content.clone();
+            }
+
+            // Link embeds are usually added by an update shortly after the message is sent
+            if let Some(embeds) = &event.embeds {
+                let embeds = embeds
+                    .iter()
+                    .filter_map(|embed| serde_json::from_value(embed.clone()).ok())
+                    .collect::<Vec<Embed>>();
+                set_embeds(&message_row.embeds, &embeds, cache, self.guild_id, images);
+            }
+
+            if let Some(edited) = event.edited_timestamp {
+                message_row.timestamp.set_text(&format!(
+                    "{} (edited {})",
+                    message_row.sent,
+                    format_timestamp(&edited),
+                ));
+            }
+        }
+    }
+
+    pub fn add_reaction(&mut self, reaction: &Reaction, user_id: UserId, images: &mut ImageLoader) {
+        if self.channel != Some(reaction.channel_id) {
+            return;
+        }
+
+        if let Some(message_row) = self.rows.get_mut(&reaction.message_id) {
+            let me = reaction.user_id == user_id;
+            match message_row
+                .reactions
+                .iter_mut()
+                .find(|existing| same_emoji(&existing.emoji, &reaction.emoji))
+            {
+                Some(existing) => {
+                    existing.count += 1;
+                    existing.me |= me;
+                }
+                None => message_row.reactions.push(ReactionCount {
+                    emoji: reaction.emoji.clone(),
+                    count: 1,
+                    me,
+                }),
+            }
+
+            set_reactions(
+                message_row,
+                reaction.channel_id,
+                reaction.message_id,
+                &self.stream,
+                images,
+            );
+        }
+    }
+
+    pub fn remove_reaction(
+        &mut self,
+        reaction: &Reaction,
+        user_id: UserId,
+        images: &mut ImageLoader,
+    ) {
+        if self.channel != Some(reaction.channel_id) {
+            return;
+        }
+
+        if let Some(message_row) = self.rows.get_mut(&reaction.message_id) {
+            for existing in message_row.reactions.iter_mut() {
+                if same_emoji(&existing.emoji, &reaction.emoji) {
+                    existing.count = existing.count.saturating_sub(1);
+                    existing.me &= reaction.user_id != user_id;
+                }
+            }
+            message_row.reactions.retain(|existing| existing.count > 0);
+
+            set_reactions(
+                message_row,
+                reaction.channel_id,
+                reaction.message_id,
+                &self.stream,
+                images,
+            );
+        }
+    }
+
+    pub fn remove_all_reactions(
+        &mut self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        images: &mut ImageLoader,
+    ) {
+        if self.channel != Some(channel_id) {
+            return;
+        }
+
+        if let Some(message_row) = self.rows.get_mut(&message_id) {
+            message_row.reactions.clear();
+            set_reactions(message_row, channel_id, message_id, &self.stream, images);
+        }
+    }
+
+    /// Lists who reacted with an emoji in the tooltip of its reaction
+    pub fn set_reaction_users(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: &ReactionType,
+        users: &[User],
+    ) {
+        if self.channel != Some(channel_id) {
+            return;
+        }
+
+        let message_row = match self.rows.get(&message_id) {
+            Some(message_row) => message_row,
+            None => return,
+        };
+        let (index, reaction) = match message_row
+            .reactions
+            .iter()
+            .enumerate()
+            .find(|(_, reaction)| same_emoji(&reaction.emoji, emoji))
+        {
+            Some(found) => found,
+            None => return,
+        };
+
+        let mut names = users
+            .iter()
+            .map(|user| user.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if reaction.count > users.len() as u64 {
+            names.push_str(&format!(
+                " and {} more",
+                reaction.count - users.len() as u64
+            ));
+        }
+
+        let pill = message_row
+            .reaction_bar
+            .get_child_at_index(index as i32)
+            .and_then(|child| child.get_child());
+        if let Some(pill) = pill {
+            pill.set_tooltip_text(Some(&names));
+        }
+    }
+
+    /// The newest displayed message sent by a user along with its content
+    pub fn last_message_by(&self, user_id: UserId) -> Option<(MessageId, &str)> {
+        self.rows
+            .iter()
+            .filter(|(_, message_row)| message_row.author_id == user_id)
+            .max_by_key(|(message_id, _)| **message_id)
+            .map(|(message_id, message_row)| (*message_id, message_row.text.as_str()))
+    }
+
+    pub fn remove(&mut self, channel_id: ChannelId, message_id: MessageId) {
+        if self.channel != Some(channel_id) {
+            return;
+        }
+
+        if let Some(message_row) = self.rows.remove(&message_id) {
+            self.list.remove(&message_row.row);
+        }
+    }
+
+    pub fn remove_bulk(&mut self, channel_id: ChannelId, message_ids: &[MessageId]) {
+        for message_id in message_ids {
+            self.remove(channel_id, *message_id);
+        }
+    }
+
+    fn try_jump(&mut self) {
+        let row = match self.jump.and_then(|message_id| self.rows.get(&message_id)) {
+            Some(message_row) => message_row.row.clone(),
+            None => return,
+        };
+        self.jump = None;
+
+        // Only once the row has been laid out is there anywhere to scroll to
+        gtk::idle_add(move || {
+            row.grab_focus();
+            glib::Continue(false)
+        });
+    }
+
+    fn insert(
+        &mut self,
+        message: &Message,
+        position: i32,
+        cache: &Cache,
+        images: &mut ImageLoader,
+    ) {
+        let row = gtk::ListBoxRow::new();
+        row.set_activatable(false);
+
+        let container = gtk::Box::new(Orientation::Horizontal, 8);
+        let avatar = Avatar::new(&message.author.name, AVATAR_RADIUS);
+        images.load(message.author.face(), &avatar);
+        container.pack_start(avatar.widget(), false, false, 0);
+
+        let body = gtk::Box::new(Orientation::Vertical, 2);
+        let header = gtk::Box::new(Orientation::Horizontal, 6);
+
+        let author = gtk::Label::new(None);
+        author.set_markup(&format!(
+            "<b>{}</b>",
+            glib::markup_escape_text(&message.author.name)
+        ));
+        header.pack_start(&author, false, false, 0);
+
+        let sent = format_timestamp(&message.timestamp);
+        let timestamp = gtk::Label::new(Some(&sent));
+        timestamp.get_style_context().add_class("dim-label");
+        if let Some(edited) = message.edited_timestamp {
+            timestamp.set_text(&format!("{} (edited {})", sent, format_timestamp(&edited)));
+        }
+        header.pack_start(&timestamp, false, false, 0);
+
+        let content = gtk::Box::new(Orientation::Vertical, 4);
+        markdown::render(
+            &content,
+            &markdown::parse(&message.content),
+            cache,
+            self.guild_id,
+            images,
+        );
+
+        let attachments = gtk::Box::new(Orientation::Vertical, 4);
+        for attachment in &message.attachments {
+            let view = AttachmentView::new(attachment, images, &self.stream);
+            attachments.pack_start(view.widget(), false, false, 0);
+        }
+
+        let embeds = gtk::Box::new(Orientation::Vertical, 4);
+        set_embeds(&embeds, &message.embeds, cache, self.guild_id, images);
+
+        let reaction_bar = gtk::FlowBox::new();
+        reaction_bar.set_selection_mode(gtk::SelectionMode::None);
+        reaction_bar.set_homogeneous(false);
+
+        body.pack_start(&header, false, false, 0);
+        body.pack_start(&content, false, false, 0);
+        body.pack_start(&attachments, false, false, 0);
+        body.pack_start(&embeds, false, false, 0);
+        body.pack_start(&reaction_bar, false, false, 0);
+        container.pack_start(&body, true, true, 0);
+
+        row.add(&container);
+        row.show_all();
+        self.list.insert(&row, position);
+
+        let message_row = MessageRow {
+            row,
+            author_id: message.author.id,
+            text: message.content.clone(),
+            content,
+            embeds,
+            reaction_bar,
+            reactions: message
+                .reactions
+                .iter()
+                .map(|reaction| ReactionCount {
+                    emoji: reaction.reaction_type.clone(),
+                    count: reaction.count,
+                    me: reaction.me,
+                })
+                .collect(),
+            timestamp,
+            sent,
+        };
+        set_reactions(
+            &message_row,
+            message.channel_id,
+            message.id,
+            &self.stream,
+            images,
+        );
+
+        self.rows.insert(message.id, message_row);
+    }
+}
+
+fn set_embeds(
+    container: &gtk::Box,
+    embeds: &[Embed],
+    cache: &Cache,
+    guild_id: Option<u64>,
+    images: &mut ImageLoader,
+) {
+    for child in container.get_children() {
+        container.remove(&child);
+    }
+
+    for embed in embeds {
+        let view = EmbedView::new(embed, cache, guild_id, images);
+        container.pack_start(view.widget(), false, false, 0);
+    }
+}
+
+/// Shows a pill for each reaction, highlighting those we reacted with, followed by a
+/// button to add another
+fn set_reactions(
+    message_row: &MessageRow,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    stream: &EventStream<Msg>,
+    images: &mut ImageLoader,
+) {
+    let bar = &message_row.reaction_bar;
+    for child in bar.get_children() {
+        bar.remove(&child);
+    }
+
+    for reaction in &message_row.reactions {
+        let content = gtk::Box::new(Orientation::Horizontal, 4);
+        match &reaction.emoji {
+            ReactionType::Custom { animated, id, name } => {
+                let name = name.as_ref().map(String::as_str).unwrap_or_default();
+                let emoji = Avatar::square(name, REACTION_EMOJI_SIZE);
+                images.load(markdown::emoji_url(id.0, *animated), &emoji);
+                content.pack_start(emoji.widget(), false, false, 0);
+            }
+            ReactionType::Unicode(emoji) => {
+                content.pack_start(&gtk::Label::new(Some(emoji.as_str())), false, false, 0)
+            }
+            _ => {}
+        }
+        content.pack_start(
+            &gtk::Label::new(Some(&reaction.count.to_string())),
+            false,
+            false,
+            0,
+        );
+
+        let pill = gtk::Button::new();
+        pill.add(&content);
+        if reaction.me {
+            pill.get_style_context().add_class("suggested-action");
+        }
+
+        let (clicked_stream, emoji, me) = (stream.clone(), reaction.emoji.clone(), reaction.me);
+        pill.connect_clicked(move |_| {
+            clicked_stream.emit(if me {
+                Msg::RemoveReaction(channel_id, message_id, emoji.clone())
+            } else {
+                Msg::AddReaction(channel_id, message_id, emoji.clone())
+            });
+        });
+
+        // Who reacted is only fetched once somebody wants to know
+        let (hovered_stream, emoji) = (stream.clone(), reaction.emoji.clone());
+        pill.connect_enter_notify_event(move |pill, _| {
+            if pill.get_tooltip_text().is_none() {
+                hovered_stream.emit(Msg::LoadReactionUsers(
+                    channel_id,
+                    message_id,
+                    emoji.clone(),
+                ));
+            }
+
+            gtk::Inhibit(false)
+        });
+
+        bar.add(&pill);
+    }
+
+    let add = gtk::Button::new_from_icon_name(Some("face-smile-symbolic"), gtk::IconSize::Button);
+    add.set_relief(gtk::ReliefStyle::None);
+    add.set_tooltip_text(Some("Add reaction"));
+    let stream = stream.clone();
+    add.connect_clicked(move |add| {
+        stream.emit(Msg::PickEmoji(
+            EmojiTarget::Reaction(channel_id, message_id),
+            add.clone().upcast(),
+        ));
+    });
+    bar.add(&add);
+
+    bar.show_all();
+}
+
+/// Custom emoji are told apart by their id alone, as their name isn't always known
+fn same_emoji(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        _ => a == b,
+    }
+}
+
+pub fn format_timestamp(timestamp: &chrono::DateTime<chrono::FixedOffset>) -> String {
+    timestamp
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
@@ -0,0 +1,147 @@
+use gtk::{
+    BoxExt, ButtonExt, ContainerExt, LabelExt, Orientation, ScrolledWindowExt, StyleContextExt,
+    TextBufferExt, TextTagExt, TextTagTableExt, TextViewExt, WidgetExt,
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, FontStyle, Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+const THEME: &str = "base16-ocean.dark";
+
+thread_local! {
+    // Loading the bundled grammars and themes is slow, so it only happens once
+    static SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static THEMES: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// A fenced code block, highlighted according to its language and scrollable sideways
+/// instead of wrapping
+pub struct CodeBlock {
+    container: gtk::Box,
+}
+
+impl CodeBlock {
+    pub fn new(language: Option<&str>, code: &str) -> Self {
+        let container = gtk::Box::new(Orientation::Vertical, 2);
+
+        let header = gtk::Box::new(Orientation::Horizontal, 6);
+        let language_label = gtk::Label::new(language);
+        language_label.set_xalign(0.0);
+        language_label.get_style_context().add_class("dim-label");
+        header.pack_start(&language_label, true, true, 0);
+
+        let copy = gtk::Button::new_with_label("Copy");
+        copy.set_relief(gtk::ReliefStyle::None);
+        let copied = code.to_string();
+        copy.connect_clicked(move |_| {
+            gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&copied);
+        });
+        header.pack_end(&copy, false, false, 0);
+
+        let view = gtk::TextView::new();
+        view.set_editable(false);
+        view.set_cursor_visible(false);
+        view.set_monospace(true);
+        view.set_wrap_mode(gtk::WrapMode::None);
+        view.set_left_margin(6);
+        view.set_right_margin(6);
+        highlight(&view, language, code);
+
+        let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+        scrolled.add(&view);
+
+        container.pack_start(&header, false, false, 0);
+        container.pack_start(&scrolled, false, false, 0);
+
+        Self { container }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+/// Fills the view with highlighted code, languages without a grammar are shown as plain
+/// text
+fn highlight(view: &gtk::TextView, language: Option<&str>, code: &str) {
+    let buffer = match view.get_buffer() {
+        Some(buffer) => buffer,
+        None => return,
+    };
+
+    SYNTAXES.with(|syntaxes| {
+        THEMES.with(|themes| {
+            let theme = match themes.themes.get(THEME) {
+                Some(theme) => theme,
+                None => return buffer.set_text(code),
+            };
+            let syntax = language
+                .and_then(|language| syntaxes.find_syntax_by_token(language))
+                .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+
+            let mut highlighter = HighlightLines::new(syntax, theme);
+            for line in LinesWithEndings::from(code) {
+                for (style, text) in highlighter.highlight(line, syntaxes) {
+                    let start = buffer.get_end_iter().get_offset();
+                    buffer.insert(&mut buffer.get_end_iter(), text);
+
+                    let (start, end) = (buffer.get_iter_at_offset(start), buffer.get_end_iter());
+                    if let Some(tag) = style_tag(&buffer, style.foreground, style.font_style) {
+                        buffer.apply_tag(&tag, &start, &end);
+                    }
+                }
+            }
+
+            if let Some(tag) = background_tag(&buffer, theme) {
+                buffer.apply_tag(&tag, &buffer.get_start_iter(), &buffer.get_end_iter());
+            }
+        })
+    });
+}
+
+/// Tags are shared by every span with the same style
+fn style_tag(
+    buffer: &gtk::TextBuffer,
+    foreground: Color,
+    font_style: FontStyle,
+) -> Option<gtk::TextTag> {
+    let table = buffer.get_tag_table()?;
+    let name = format!("{}-{}", hex(foreground), font_style.bits());
+    if let Some(tag) = table.lookup(&name) {
+        return Some(tag);
+    }
+
+    let tag = gtk::TextTag::new(Some(&name));
+    tag.set_property_foreground(Some(&hex(foreground)));
+    if font_style.contains(FontStyle::BOLD) {
+        tag.set_property_weight(700);
+    }
+    if font_style.contains(FontStyle::ITALIC) {
+        tag.set_property_style(pango::Style::Italic);
+    }
+    if font_style.contains(FontStyle::UNDERLINE) {
+        tag.set_property_underline(pango::Underline::Single);
+    }
+    table.add(&tag);
+
+    Some(tag)
+}
+
+fn background_tag(buffer: &gtk::TextBuffer, theme: &Theme) -> Option<gtk::TextTag> {
+    let background = theme.settings.background?;
+    let table = buffer.get_tag_table()?;
+
+    let tag = gtk::TextTag::new(Some("background"));
+    tag.set_property_paragraph_background(Some(&hex(background)));
+    table.add(&tag);
+
+    Some(tag)
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
@@ -0,0 +1,430 @@
+use super::{emoji_completion::EmojiCompletion, emoji_picker, Msg};
+use crate::backend::{UploadFile, UploadState};
+use glib::ObjectExt;
+use gtk::{
+    BoxExt, ButtonExt, ContainerExt, Inhibit, LabelExt, Orientation, ProgressBarExt,
+    ScrolledWindowExt, StyleContextExt, TextBufferExt, TextViewExt, WidgetExt,
+};
+use serenity::model::{channel::ReactionType, id::MessageId};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+/// Discord rejects messages longer than this many characters
+const MAX_MESSAGE_LEN: usize = 2000;
+/// Discord rejects files larger than this without Nitro
+const MAX_UPLOAD_SIZE: u64 = 8 * 1024 * 1024;
+/// How many files can wait in the tray at once
+const MAX_ATTACHMENTS: usize = 10;
+/// How often the bar of the file being uploaded moves
+const PULSE_INTERVAL: u32 = 100;
+
+/// A file in the tray, waiting to be sent or being uploaded
+struct Attachment {
+    file: Option<UploadFile>,
+    row: gtk::Box,
+    progress: gtk::ProgressBar,
+    remove: gtk::Button,
+    /// Whether the file is being uploaded right now, its bar pulses until then
+    uploading: Rc<Cell<bool>>,
+}
+
+/// Files being uploaded one after the other, the message's text going out with the first
+/// one which makes it
+struct Upload {
+    /// By their index, those done are taken out
+    attachments: Vec<Option<Attachment>>,
+    /// The text until one of the files was sent with it
+    content: Option<String>,
+}
+
+/// The message input below the chat pane
+pub struct ComposeBox {
+    container: gtk::Box,
+    text_view: gtk::TextView,
+    status: gtk::Label,
+    editing: Option<MessageId>,
+    attach: gtk::Button,
+    emoji: gtk::Button,
+    completion: EmojiCompletion,
+    tray: gtk::Box,
+    /// Files which go out with the next message
+    pending: Rc<RefCell<Vec<Attachment>>>,
+    /// Files being uploaded, by upload id
+    uploads: HashMap<u64, Upload>,
+    next_upload: u64,
+}
+
+impl ComposeBox {
+    pub fn new() -> Self {
+        let container = gtk::Box::new(Orientation::Vertical, 2);
+
+        let status = gtk::Label::new(None);
+        status.set_xalign(0.0);
+        status.set_line_wrap(true);
+        status.set_no_show_all(true);
+
+        let text_view = gtk::TextView::new();
+        text_view.set_wrap_mode(gtk::WrapMode::Word);
+        text_view.set_editable(true);
+        text_view.set_can_focus(true);
+        let scrolled_text_view = gtk::ScrolledWindow::new(
+            gtk::NONE_ADJUSTMENT,
+            Some(&gtk::Adjustment::new(0.1, 0.1, 1.0, 0.1, 0.1, 0.1)),
+        );
+        scrolled_text_view.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+        scrolled_text_view.add(&text_view);
+        let completion = EmojiCompletion::new(&text_view);
+
+        let tray = gtk::Box::new(Orientation::Horizontal, 6);
+        tray.set_no_show_all(true);
+
+        let attach = gtk::Button::new_from_icon_name(
+            Some("mail-attachment-symbolic"),
+            gtk::IconSize::Button,
+        );
+        attach.set_tooltip_text(Some("Attach files"));
+        attach.set_valign(gtk::Align::Start);
+
+        let emoji =
+            gtk::Button::new_from_icon_name(Some("face-smile-symbolic"), gtk::IconSize::Button);
+        emoji.set_tooltip_text(Some("Insert emoji"));
+        emoji.set_valign(gtk::Align::Start);
+
+        let input = gtk::Box::new(Orientation::Horizontal, 4);
+        input.pack_start(&attach, false, false, 0);
+        input.pack_start(&scrolled_text_view, true, true, 0);
+        input.pack_start(&emoji, false, false, 0);
+
+        container.pack_start(&status, false, false, 0);
+        container.pack_start(&tray, false, false, 0);
+        container.pack_start(&input, true, true, 0);
+
+        Self {
+            container,
+            text_view,
+            status,
+            editing: None,
+            attach,
+            emoji,
+            completion,
+            tray,
+            pending: Rc::new(RefCell::new(Vec::new())),
+            uploads: HashMap::new(),
+            next_upload: 0,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn text_view(&self) -> &gtk::TextView {
+        &self.text_view
+    }
+
+    pub fn attach_button(&self) -> &gtk::Button {
+        &self.attach
+    }
+
+    pub fn emoji_button(&self) -> &gtk::Button {
+        &self.emoji
+    }
+
+    /// The custom emoji suggested while typing a shortcode
+    pub fn set_custom_emoji(&self, emoji: Vec<(String, ReactionType)>) {
+        self.completion.set_custom_emoji(emoji);
+    }
+
+    /// Inserts an emoji at the cursor
+    pub fn insert_emoji(&self, emoji: &ReactionType) {
+        if let Some(buffer) = self.text_view.get_buffer() {
+            buffer.insert_at_cursor(&emoji_picker::message_text(emoji));
+        }
+        self.text_view.grab_focus();
+    }
+
+    /// The message currently being edited, if any
+    pub fn editing(&self) -> Option<MessageId> {
+        self.editing
+    }
+
+    pub fn text(&self) -> String {
+        self.text_view
+            .get_buffer()
+            .and_then(|buffer| {
+                let (start, end) = buffer.get_bounds();
+                buffer.get_text(&start, &end, false)
+            })
+            .map(|text| text.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        is_empty(&self.text_view)
+    }
+
+    /// Checks the composed text before it's sent, returning the content to send
+    pub fn content(&self) -> Result<Option<String>, String> {
+        let text = self.text();
+        let content = text.trim();
+
+        if content.is_empty() {
+            Ok(None)
+        } else if content.chars().count() > MAX_MESSAGE_LEN {
+            Err(format!(
+                "Messages can be at most {} characters long",
+                MAX_MESSAGE_LEN
+            ))
+        } else {
+            Ok(Some(content.to_string()))
+        }
+    }
+
+    pub fn has_attachments(&self) -> bool {
+        !self.pending.borrow().is_empty()
+    }
+
+    /// Adds a file to the tray, unless Discord would reject it
+    pub fn add_attachment(&self, file: UploadFile) -> Result<(), String> {
+        let name = file.name();
+        match file.size() {
+            Some(size) if size > MAX_UPLOAD_SIZE => {
+                return Err(format!(
+                    "{} is larger than the upload limit of {} MiB",
+                    name,
+                    MAX_UPLOAD_SIZE / 1024 / 1024
+                ))
+            }
+            Some(_) => {}
+            None => return Err(format!("{} can't be read", name)),
+        }
+        if self.pending.borrow().len() >= MAX_ATTACHMENTS {
+            return Err(format!(
+                "At most {} files can be sent at once",
+                MAX_ATTACHMENTS
+            ));
+        }
+
+        let row = gtk::Box::new(Orientation::Vertical, 2);
+        let header = gtk::Box::new(Orientation::Horizontal, 2);
+        let label = gtk::Label::new(Some(&name));
+        label.set_ellipsize(pango::EllipsizeMode::Middle);
+        label.set_max_width_chars(24);
+        header.pack_start(&label, true, true, 0);
+
+        let remove =
+            gtk::Button::new_from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+        remove.set_relief(gtk::ReliefStyle::None);
+        remove.set_tooltip_text(Some("Remove"));
+        header.pack_end(&remove, false, false, 0);
+
+        let progress = gtk::ProgressBar::new();
+        progress.set_show_text(true);
+        progress.set_no_show_all(true);
+
+        row.pack_start(&header, false, false, 0);
+        row.pack_start(&progress, false, false, 0);
+        row.get_style_context().add_class("frame");
+
+        let (pending, tray, removed) = (Rc::clone(&self.pending), self.tray.clone(), row.clone());
+        remove.connect_clicked(move |_| {
+            pending
+                .borrow_mut()
+                .retain(|attachment| attachment.row != removed);
+            removed.destroy();
+            if tray.get_children().is_empty() {
+                tray.hide();
+            }
+        });
+
+        self.tray.pack_start(&row, false, false, 0);
+        row.show_all();
+        self.tray.show();
+
+        self.pending.borrow_mut().push(Attachment {
+            file: Some(file),
+            row,
+            progress,
+            remove,
+            uploading: Rc::new(Cell::new(false)),
+        });
+
+        Ok(())
+    }
+
+    /// Takes the files in the tray for uploading along with the text they go out with,
+    /// returning the id their progress is reported under. The text comes back if none of
+    /// them could be sent
+    pub fn start_upload(&mut self, content: Option<String>) -> (u64, Vec<UploadFile>) {
+        let upload_id = self.next_upload;
+        self.next_upload += 1;
+
+        let mut files = Vec::new();
+        let mut attachments = Vec::new();
+        for mut attachment in self.pending.borrow_mut().drain(..) {
+            files.extend(attachment.file.take());
+            attachment.remove.hide();
+            attachment.progress.set_text(Some("Queued"));
+            attachment.progress.show();
+            attachments.push(Some(attachment));
+        }
+        self.uploads.insert(
+            upload_id,
+            Upload {
+                attachments,
+                content,
+            },
+        );
+
+        (upload_id, files)
+    }
+
+    /// Shows how the upload of a file went, sent files leave the tray while failed ones
+    /// stay until they're dismissed
+    pub fn upload_progress(&mut self, upload_id: u64, index: usize, state: &UploadState) {
+        let upload = match self.uploads.get_mut(&upload_id) {
+            Some(upload) => upload,
+            None => return,
+        };
+        let count = upload.attachments.len();
+
+        match state {
+            UploadState::Uploading => {
+                if let Some(Some(attachment)) = upload.attachments.get(index) {
+                    let text = format!("Uploading {} of {}", index + 1, count);
+                    attachment.progress.set_text(Some(text.as_str()));
+                    pulse(&attachment.progress, &attachment.uploading);
+                }
+            }
+            UploadState::Sent => {
+                if let Some(attachment) = upload.attachments.get_mut(index).and_then(Option::take) {
+                    attachment.uploading.set(false);
+                    attachment.row.destroy();
+                }
+                upload.content = None;
+            }
+            UploadState::Failed(err) => {
+                if let Some(attachment) = upload.attachments.get_mut(index).and_then(Option::take) {
+                    attachment.uploading.set(false);
+                    attachment.progress.set_text(Some("Failed"));
+                    attachment.progress.set_fraction(0.0);
+                    attachment.remove.show();
+                }
+                self.show_error(&format!("Upload failed: {}", err));
+            }
+        }
+
+        let done = self.uploads.get(&upload_id).map_or(false, |upload| {
+            upload.attachments.iter().all(Option::is_none)
+        });
+        if done {
+            let content = self
+                .uploads
+                .remove(&upload_id)
+                .and_then(|upload| upload.content);
+            if let Some(content) = content {
+                self.restore(None, &content);
+            }
+        }
+
+        if self.tray.get_children().is_empty() {
+            self.tray.hide();
+        }
+    }
+
+    /// Loads a previously sent message into the box for editing
+    pub fn start_edit(&mut self, message_id: MessageId, content: &str) {
+        self.editing = Some(message_id);
+        self.set_text(content);
+        self.show_status("Editing message, press Escape to cancel", false);
+    }
+
+    /// Puts back a message which failed to send, unless something else was typed since
+    pub fn restore(&mut self, editing: Option<MessageId>, content: &str) {
+        if self.is_empty() {
+            self.editing = editing;
+            self.set_text(content);
+        }
+    }
+
+    /// Empties the box and leaves edit mode
+    pub fn clear(&mut self) {
+        self.editing = None;
+        self.set_text("");
+        self.status.hide();
+    }
+
+    pub fn show_error(&self, error: &str) {
+        self.show_status(error, true);
+    }
+
+    pub fn show_info(&self, info: &str) {
+        self.show_status(info, false);
+    }
+
+    fn show_status(&self, status: &str, is_error: bool) {
+        let style = self.status.get_style_context();
+        if is_error {
+            style.add_class("error");
+        } else {
+            style.remove_class("error");
+        }
+
+        self.status.set_text(status);
+        self.status.show();
+    }
+
+    fn set_text(&self, text: &str) {
+        if let Some(buffer) = self.text_view.get_buffer() {
+            buffer.set_text(text);
+        }
+    }
+}
+
+/// Maps key presses in the compose box to messages, Enter sends while Shift+Enter
+/// inserts a newline, Up in an empty box edits the last sent message and pasting an image
+/// attaches it
+pub fn key_press(text_view: &gtk::TextView, key: &gdk::EventKey) -> (Option<Msg>, Inhibit) {
+    use gdk::enums::key;
+
+    let shift = key.get_state().contains(gdk::ModifierType::SHIFT_MASK);
+    let control = key.get_state().contains(gdk::ModifierType::CONTROL_MASK);
+
+    match key.get_keyval() {
+        key::Return | key::KP_Enter if !shift => (Some(Msg::Send), Inhibit(true)),
+        key::Up if is_empty(text_view) => (Some(Msg::EditLast), Inhibit(true)),
+        key::Escape => (Some(Msg::CancelEdit), Inhibit(true)),
+        // Pasted images become attachments, anything else is pasted as usual
+        key::v | key::V
+            if control
+                && gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).wait_is_image_available() =>
+        {
+            (Some(Msg::PasteImage), Inhibit(true))
+        }
+        _ => (None, Inhibit(false)),
+    }
+}
+
+/// Keeps moving the bar of a file being uploaded, as how far along it is isn't known
+fn pulse(progress: &gtk::ProgressBar, uploading: &Rc<Cell<bool>>) {
+    uploading.set(true);
+    progress.pulse();
+
+    let (progress, uploading) = (progress.downgrade(), Rc::clone(uploading));
+    gtk::timeout_add(PULSE_INTERVAL, move || match progress.upgrade() {
+        Some(progress) if uploading.get() => {
+            progress.pulse();
+            glib::Continue(true)
+        }
+        _ => glib::Continue(false),
+    });
+}
+
+fn is_empty(text_view: &gtk::TextView) -> bool {
+    text_view
+        .get_buffer()
+        .map_or(true, |buffer| buffer.get_char_count() == 0)
+}
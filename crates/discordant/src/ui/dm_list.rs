@@ -0,0 +1,148 @@
+use super::{
+    images::{Avatar, ImageLoader},
+    presence,
+    unread::{Unread, UnreadLabel},
+};
+use crate::backend::{Cache, ChannelKind};
+use gtk::{
+    BoxExt, ContainerExt, LabelExt, ListBoxExt, Orientation, ScrolledWindowExt, StyleContextExt,
+    WidgetExt,
+};
+use serenity::model::id::ChannelId;
+use std::collections::HashMap;
+
+const AVATAR_RADIUS: f64 = 16.0;
+
+/// Our DMs and group DMs with the most recently active first, listed in place of the
+/// channels while Home is selected
+pub struct DmList {
+    container: gtk::Box,
+    list: gtk::ListBox,
+    new_button: gtk::Button,
+    rows: Vec<ChannelId>,
+    labels: HashMap<ChannelId, UnreadLabel>,
+}
+
+impl DmList {
+    pub fn new() -> Self {
+        let new_button = gtk::Button::new_with_label("New Message");
+        let list = gtk::ListBox::new();
+
+        let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled.add(&list);
+
+        let container = gtk::Box::new(Orientation::Vertical, 2);
+        container.pack_start(&new_button, false, false, 0);
+        container.pack_start(&scrolled, true, true, 0);
+
+        Self {
+            container,
+            list,
+            new_button,
+            rows: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn list(&self) -> &gtk::ListBox {
+        &self.list
+    }
+
+    /// Starts a DM with someone new
+    pub fn new_button(&self) -> &gtk::Button {
+        &self.new_button
+    }
+
+    pub fn channel_at(&self, row: usize) -> Option<ChannelId> {
+        self.rows.get(row).cloned()
+    }
+
+    /// Replaces the listed DMs, keeping the given one selected if it's still listed
+    pub fn set_channels(
+        &mut self,
+        cache: &Cache,
+        selected: Option<ChannelId>,
+        unread: &HashMap<ChannelId, Unread>,
+        images: &mut ImageLoader,
+    ) {
+        for child in self.list.get_children() {
+            self.list.remove(&child);
+        }
+        self.rows.clear();
+        self.labels.clear();
+
+        let mut dms = cache
+            .dm_ids()
+            .into_iter()
+            .filter_map(|channel_id| Some((channel_id, (*cache.dm(channel_id)?).clone())))
+            .collect::<Vec<_>>();
+        // Never active DMs have no last message and go last
+        dms.sort_by_key(|(channel_id, dm)| std::cmp::Reverse((dm.last_message_id, *channel_id)));
+
+        for (channel_id, dm) in dms {
+            let channel_id = ChannelId(channel_id);
+            let name = cache.dm_name(&dm);
+
+            let avatar = Avatar::new(&name, AVATAR_RADIUS);
+            let first_recipient = dm
+                .recipients
+                .first()
+                .and_then(|user_id| cache.user(*user_id));
+            if let Some(recipient) = first_recipient {
+                images.load(recipient.avatar.clone(), &avatar);
+            }
+
+            let label = UnreadLabel::new(&name);
+            label.set(unread.get(&channel_id).cloned().unwrap_or_default());
+
+            // DMs show how their recipient is doing, groups how many are in them
+            let subtitle = gtk::Label::new(None);
+            subtitle.set_xalign(0.0);
+            subtitle.get_style_context().add_class("dim-label");
+            match (dm.kind, dm.recipients.first()) {
+                (ChannelKind::Group, _) => {
+                    subtitle.set_text(&format!("{} Members", dm.recipients.len() + 1))
+                }
+                (_, Some(user_id)) => {
+                    subtitle.set_markup(&presence::status_markup(cache.status(*user_id)))
+                }
+                _ => {}
+            }
+
+            let text = gtk::Box::new(Orientation::Vertical, 0);
+            text.pack_start(label.widget(), false, false, 0);
+            text.pack_start(&subtitle, false, false, 0);
+
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            row.pack_start(avatar.widget(), false, false, 0);
+            row.pack_start(&text, true, true, 0);
+
+            self.list.add(&row);
+            self.rows.push(channel_id);
+            self.labels.insert(channel_id, label);
+        }
+
+        self.list.show_all();
+
+        let selected = self
+            .rows
+            .iter()
+            .position(|channel_id| Some(*channel_id) == selected)
+            .and_then(|row| self.list.get_row_at_index(row as i32));
+        if let Some(row) = selected {
+            self.list.select_row(Some(&row));
+        }
+    }
+
+    /// Shows what's unread in a DM, if it's listed
+    pub fn set_unread(&self, channel_id: ChannelId, unread: Unread) {
+        if let Some(label) = self.labels.get(&channel_id) {
+            label.set(unread);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use super::{
+    chat::format_timestamp,
+    images::{Avatar, ImageLoader},
+    markdown,
+};
+use crate::backend::Cache;
+use gtk::{BoxExt, GridExt, LabelExt, Orientation, StyleContextExt, WidgetExt};
+use serenity::model::channel::Embed;
+
+const IMAGE_MAX_WIDTH: f64 = 400.0;
+const IMAGE_MAX_HEIGHT: f64 = 300.0;
+const THUMBNAIL_SIZE: f64 = 80.0;
+const ICON_RADIUS: f64 = 10.0;
+/// Inline fields are laid out this many to a row, like the official client does
+const INLINE_FIELDS_PER_ROW: i32 = 3;
+/// The bar colour of embeds that didn't set one
+const DEFAULT_COLOR: (f64, f64, f64) = (0.31, 0.33, 0.36);
+
+/// A rich or link embed attached to a message, drawn with its colour bar down the left
+pub struct EmbedView {
+    container: gtk::Box,
+}
+
+impl EmbedView {
+    pub fn new(
+        embed: &Embed,
+        cache: &Cache,
+        guild_id: Option<u64>,
+        images: &mut ImageLoader,
+    ) -> Self {
+        let container = gtk::Box::new(Orientation::Horizontal, 8);
+        container.pack_start(&color_bar(embed), false, false, 0);
+
+        let body = gtk::Box::new(Orientation::Vertical, 4);
+
+        if let Some(provider) = &embed.provider {
+            let label = link_label(&provider.name, provider.url.as_ref(), false);
+            label.get_style_context().add_class("dim-label");
+            body.pack_start(&label, false, false, 0);
+        }
+
+        if let Some(author) = &embed.author {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            if let Some(icon_url) = author.proxy_icon_url.as_ref().or(author.icon_url.as_ref()) {
+                let icon = Avatar::new(&author.name, ICON_RADIUS);
+                images.load(icon_url.clone(), &icon);
+                row.pack_start(icon.widget(), false, false, 0);
+            }
+            row.pack_start(
+                &link_label(&author.name, author.url.as_ref(), true),
+                false,
+                false,
+                0,
+            );
+            body.pack_start(&row, false, false, 0);
+        }
+
+        if let Some(title) = &embed.title {
+            body.pack_start(
+                &link_label(title, embed.url.as_ref(), true),
+                false,
+                false,
+                0,
+            );
+        }
+
+        if let Some(description) = &embed.description {
+            let content = gtk::Box::new(Orientation::Vertical, 4);
+            markdown::render(
+                &content,
+                &markdown::parse(description),
+                cache,
+                guild_id,
+                images,
+            );
+            body.pack_start(&content, false, false, 0);
+        }
+
+        if !embed.fields.is_empty() {
+            body.pack_start(&fields(embed, cache, guild_id, images), false, false, 0);
+        }
+
+        if let Some(image) = &embed.image {
+            let picture = picture(image.width, image.height, IMAGE_MAX_WIDTH, IMAGE_MAX_HEIGHT);
+            images.load(proxied(&image.proxy_url, &image.url), &picture);
+            body.pack_start(picture.widget(), false, false, 0);
+        } else if let Some(video) = &embed.video {
+            body.pack_start(
+                &link_label("Play video", Some(&video.url), false),
+                false,
+                false,
+                0,
+            );
+        }
+
+        if embed.footer.is_some() || embed.timestamp.is_some() {
+            body.pack_start(&footer(embed, images), false, false, 0);
+        }
+
+        container.pack_start(&body, true, true, 0);
+
+        if let Some(thumbnail) = &embed.thumbnail {
+            let picture = picture(
+                thumbnail.width,
+                thumbnail.height,
+                THUMBNAIL_SIZE,
+                THUMBNAIL_SIZE,
+            );
+            images.load(proxied(&thumbnail.proxy_url, &thumbnail.url), &picture);
+            picture.widget().set_valign(gtk::Align::Start);
+            container.pack_start(picture.widget(), false, false, 0);
+        }
+
+        container.show_all();
+
+        Self { container }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+fn color_bar(embed: &Embed) -> gtk::DrawingArea {
+    let (red, green, blue) = match embed.colour.tuple() {
+        (0, 0, 0) => DEFAULT_COLOR,
+        (red, green, blue) => (
+            f64::from(red) / 255.0,
+            f64::from(green) / 255.0,
+            f64::from(blue) / 255.0,
+        ),
+    };
+
+    let bar = gtk::DrawingArea::new();
+    bar.set_size_request(4, -1);
+    bar.connect_draw(move |_, g| {
+        g.set_source_rgb(red, green, blue);
+        g.paint();
+
+        gtk::Inhibit(false)
+    });
+
+    bar
+}
+
+/// Lays the fields out in a grid, inline fields share rows while the others span them
+fn fields(
+    embed: &Embed,
+    cache: &Cache,
+    guild_id: Option<u64>,
+    images: &mut ImageLoader,
+) -> gtk::Grid {
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(12);
+    grid.set_row_spacing(6);
+
+    let (mut row, mut column) = (0, 0);
+    for field in embed.fields.iter() {
+        if !field.inline || column == INLINE_FIELDS_PER_ROW {
+            if column != 0 {
+                row += 1;
+            }
+            column = 0;
+        }
+
+        let cell = gtk::Box::new(Orientation::Vertical, 2);
+        let name = gtk::Label::new(None);
+        name.set_markup(&format!("<b>{}</b>", glib::markup_escape_text(&field.name)));
+        name.set_xalign(0.0);
+        name.set_line_wrap(true);
+        cell.pack_start(&name, false, false, 0);
+
+        let value = gtk::Box::new(Orientation::Vertical, 2);
+        markdown::render(
+            &value,
+            &markdown::parse(&field.value),
+            cache,
+            guild_id,
+            images,
+        );
+        cell.pack_start(&value, false, false, 0);
+
+        if field.inline {
+            grid.attach(&cell, column, row, 1, 1);
+            column += 1;
+        } else {
+            grid.attach(&cell, 0, row, INLINE_FIELDS_PER_ROW, 1);
+            row += 1;
+        }
+    }
+
+    grid
+}
+
+fn footer(embed: &Embed, images: &mut ImageLoader) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+
+    let mut text = Vec::new();
+    if let Some(footer) = &embed.footer {
+        if let Some(icon_url) = footer.proxy_icon_url.as_ref().or(footer.icon_url.as_ref()) {
+            let icon = Avatar::new(&footer.text, ICON_RADIUS);
+            images.load(icon_url.clone(), &icon);
+            row.pack_start(icon.widget(), false, false, 0);
+        }
+
+        text.push(footer.text.clone());
+    }
+
+    let timestamp = embed
+        .timestamp
+        .as_ref()
+        .and_then(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).ok());
+    if let Some(timestamp) = timestamp {
+        text.push(format_timestamp(&timestamp));
+    }
+
+    let label = gtk::Label::new(Some(&text.join(" \u{2022} ")));
+    label.set_xalign(0.0);
+    label.set_line_wrap(true);
+    label.get_style_context().add_class("dim-label");
+    row.pack_start(&label, false, false, 0);
+
+    row
+}
+
+/// A label which links to the url if there is one
+fn link_label(text: &str, url: Option<&String>, bold: bool) -> gtk::Label {
+    let escaped = glib::markup_escape_text(text);
+    let markup = match url {
+        Some(url) => format!(
+            "<a href=\"{}\">{}</a>",
+            glib::markup_escape_text(url),
+            escaped
+        ),
+        None => escaped.to_string(),
+    };
+
+    let label = gtk::Label::new(None);
+    label.set_markup(&if bold {
+        format!("<b>{}</b>", markup)
+    } else {
+        markup
+    });
+    label.set_xalign(0.0);
+    label.set_line_wrap(true);
+
+    label
+}
+
+/// An image scaled down to fit within the bounds, keeping its aspect ratio
+pub fn picture(width: u64, height: u64, max_width: f64, max_height: f64) -> Avatar {
+    // Not every embed knows the size of its images
+    if width == 0 || height == 0 {
+        return Avatar::sized("", max_width, max_height);
+    }
+
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let scale = (max_width / width).min(max_height / height).min(1.0);
+
+    Avatar::sized("", (width * scale).round(), (height * scale).round())
+}
+
+/// Discord proxies embedded images, which is preferred when it's available
+fn proxied(proxy_url: &str, url: &str) -> String {
+    if proxy_url.is_empty() {
+        url.to_string()
+    } else {
+        proxy_url.to_string()
+    }
+}
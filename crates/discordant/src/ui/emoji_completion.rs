@@ -0,0 +1,231 @@
+use super::{emoji_data, emoji_picker};
+use gtk::{
+    BoxExt, ContainerExt, Inhibit, LabelExt, ListBoxExt, ListBoxRowExt, Orientation, PopoverExt,
+    StyleContextExt, TextBufferExt, TextViewExt, WidgetExt,
+};
+use serenity::model::channel::ReactionType;
+use std::{cell::RefCell, rc::Rc};
+
+/// How many suggestions are shown at once
+const MAX_SUGGESTIONS: usize = 8;
+/// Suggestions start after this many characters of a shortcode
+const MIN_QUERY_LEN: usize = 2;
+
+/// Suggests emoji while a `:shortcode` is typed into a text view, Tab or Enter replaces
+/// it with the highlighted suggestion
+pub struct EmojiCompletion {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    text_view: gtk::TextView,
+    popover: gtk::Popover,
+    list: gtk::ListBox,
+    custom_emoji: RefCell<Vec<(String, ReactionType)>>,
+    suggestions: RefCell<Vec<ReactionType>>,
+}
+
+impl EmojiCompletion {
+    /// Has to be created before anything else handles key presses of the text view, so
+    /// that it gets to handle them first while suggesting
+    pub fn new(text_view: &gtk::TextView) -> Self {
+        let popover = gtk::Popover::new(Some(text_view));
+        popover.set_modal(false);
+        popover.set_position(gtk::PositionType::Top);
+
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::Browse);
+        popover.add(&list);
+
+        let inner = Rc::new(Inner {
+            text_view: text_view.clone(),
+            popover,
+            list,
+            custom_emoji: RefCell::new(Vec::new()),
+            suggestions: RefCell::new(Vec::new()),
+        });
+
+        if let Some(buffer) = text_view.get_buffer() {
+            let weak = Rc::downgrade(&inner);
+            buffer.connect_changed(move |_| {
+                if let Some(inner) = weak.upgrade() {
+                    inner.update();
+                }
+            });
+        }
+
+        let weak = Rc::downgrade(&inner);
+        text_view.connect_key_press_event(move |_, key| {
+            weak.upgrade()
+                .map_or(Inhibit(false), |inner| inner.key_press(key))
+        });
+
+        let weak = Rc::downgrade(&inner);
+        inner.list.connect_row_activated(move |_, row| {
+            if let Some(inner) = weak.upgrade() {
+                inner.complete(row.get_index() as usize);
+            }
+        });
+
+        Self { inner }
+    }
+
+    /// The custom emoji which can be suggested along with the unicode ones
+    pub fn set_custom_emoji(&self, emoji: Vec<(String, ReactionType)>) {
+        *self.inner.custom_emoji.borrow_mut() = emoji;
+    }
+}
+
+impl Inner {
+    /// The offset of the colon starting the shortcode being typed, and what's been typed
+    /// of it so far
+    fn query(&self) -> Option<(i32, String)> {
+        let buffer = self.text_view.get_buffer()?;
+        let cursor = buffer.get_iter_at_mark(&buffer.get_insert()?);
+        let mut line_start = cursor.clone();
+        line_start.set_line_offset(0);
+        let before = buffer.get_text(&line_start, &cursor, false)?.to_string();
+
+        let colon = before.rfind(':')?;
+        let query = &before[colon + 1..];
+        let starts_word = before[..colon]
+            .chars()
+            .next_back()
+            .map_or(true, char::is_whitespace);
+        let is_shortcode = query.chars().count() >= MIN_QUERY_LEN
+            && query
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '+');
+        if !starts_word || !is_shortcode {
+            return None;
+        }
+
+        let offset = line_start.get_offset() + before[..colon].chars().count() as i32;
+        Some((offset, query.to_lowercase()))
+    }
+
+    fn update(&self) {
+        let query = match self.query() {
+            Some((_, query)) => query,
+            None => return self.hide(),
+        };
+
+        let mut matches = emoji_data::all()
+            .map(|(shortcode, unicode)| (shortcode, ReactionType::Unicode(unicode.to_string())))
+            .chain(self.custom_emoji.borrow().iter().cloned())
+            .filter(|(shortcode, _)| shortcode.to_lowercase().contains(&query))
+            .collect::<Vec<_>>();
+        // Shortcodes starting with the query come before those merely containing it
+        matches.sort_by_key(|(shortcode, _)| !shortcode.to_lowercase().starts_with(&query));
+        matches.truncate(MAX_SUGGESTIONS);
+
+        if matches.is_empty() {
+            return self.hide();
+        }
+
+        for child in self.list.get_children() {
+            self.list.remove(&child);
+        }
+        for (shortcode, emoji) in matches.iter() {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            if let ReactionType::Unicode(unicode) = emoji {
+                row.pack_start(&gtk::Label::new(Some(unicode.as_str())), false, false, 0);
+            }
+            let label = gtk::Label::new(Some(&format!(":{}:", shortcode)));
+            if let ReactionType::Custom { .. } = emoji {
+                label.get_style_context().add_class("dim-label");
+            }
+            row.pack_start(&label, false, false, 0);
+            self.list.add(&row);
+        }
+        *self.suggestions.borrow_mut() = matches.into_iter().map(|(_, emoji)| emoji).collect();
+        self.select(0);
+
+        // Point at the cursor
+        if let Some(buffer) = self.text_view.get_buffer() {
+            if let Some(insert) = buffer.get_insert() {
+                let location = self
+                    .text_view
+                    .get_iter_location(&buffer.get_iter_at_mark(&insert));
+                let (x, y) = self.text_view.buffer_to_window_coords(
+                    gtk::TextWindowType::Widget,
+                    location.x,
+                    location.y,
+                );
+                self.popover.set_pointing_to(&gdk::Rectangle {
+                    x,
+                    y,
+                    width: 1,
+                    height: location.height,
+                });
+            }
+        }
+
+        self.list.show_all();
+        self.popover.show();
+    }
+
+    fn key_press(&self, key: &gdk::EventKey) -> Inhibit {
+        use gdk::enums::key;
+
+        if !self.popover.get_visible() {
+            return Inhibit(false);
+        }
+
+        let selected = self
+            .list
+            .get_selected_row()
+            .map_or(0, |row| row.get_index());
+        match key.get_keyval() {
+            key::Up => self.select(selected - 1),
+            key::Down => self.select(selected + 1),
+            key::Tab | key::Return | key::KP_Enter => self.complete(selected as usize),
+            key::Escape => self.hide(),
+            _ => return Inhibit(false),
+        }
+
+        Inhibit(true)
+    }
+
+    /// Highlights a suggestion, wrapping around at either end
+    fn select(&self, index: i32) {
+        let count = self.suggestions.borrow().len() as i32;
+        if count == 0 {
+            return;
+        }
+
+        let row = self.list.get_row_at_index((index + count) % count);
+        self.list.select_row(row.as_ref());
+    }
+
+    /// Replaces the shortcode being typed with a suggestion
+    fn complete(&self, index: usize) {
+        let emoji = match self.suggestions.borrow().get(index) {
+            Some(emoji) => emoji.clone(),
+            None => return,
+        };
+        let (start, buffer) = match (self.query(), self.text_view.get_buffer()) {
+            (Some((start, _)), Some(buffer)) => (start, buffer),
+            _ => return,
+        };
+        let insert = match buffer.get_insert() {
+            Some(insert) => insert,
+            None => return,
+        };
+
+        let mut start = buffer.get_iter_at_offset(start);
+        let mut cursor = buffer.get_iter_at_mark(&insert);
+        buffer.delete(&mut start, &mut cursor);
+        buffer.insert(
+            &mut start,
+            &format!("{} ", emoji_picker::message_text(&emoji)),
+        );
+
+        self.hide();
+    }
+
+    fn hide(&self) {
+        self.suggestions.borrow_mut().clear();
+        self.popover.hide();
+    }
+}
@@ -0,0 +1,63 @@
+use emojis::{Emoji, Group};
+
+/// Every unicode emoji grouped into the picker's categories, in Unicode's order. Each
+/// comes with the shortcode Discord knows it by
+pub fn categories() -> impl Iterator<Item = (&'static str, Vec<(String, &'static str)>)> {
+    Group::iter().map(|group| {
+        let emoji = group
+            .emojis()
+            .map(|emoji| (shortcode_of(emoji), emoji.as_str()))
+            .collect();
+
+        (title(group), emoji)
+    })
+}
+
+/// The shortcode of a unicode emoji, skin tones included
+pub fn shortcode(emoji: &str) -> Option<String> {
+    emojis::get(emoji).map(shortcode_of)
+}
+
+/// Every unicode emoji once for each of its shortcodes, as some go by several
+pub fn all() -> impl Iterator<Item = (String, &'static str)> {
+    emojis::iter().flat_map(|emoji| {
+        let shortcodes = match emoji.shortcodes().count() {
+            0 => vec![shortcode_of(emoji)],
+            _ => emoji.shortcodes().map(str::to_string).collect(),
+        };
+
+        shortcodes
+            .into_iter()
+            .map(move |shortcode| (shortcode, emoji.as_str()))
+    })
+}
+
+/// The first of an emoji's shortcodes. The few without any, like the newest emoji and
+/// those with skin tones, get one made from their name
+fn shortcode_of(emoji: &Emoji) -> String {
+    if let Some(shortcode) = emoji.shortcode() {
+        return shortcode.to_string();
+    }
+
+    emoji
+        .name()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn title(group: Group) -> &'static str {
+    match group {
+        Group::SmileysAndEmotion => "Smileys & Emotion",
+        Group::PeopleAndBody => "People & Body",
+        Group::AnimalsAndNature => "Animals & Nature",
+        Group::FoodAndDrink => "Food & Drink",
+        Group::TravelAndPlaces => "Travel & Places",
+        Group::Activities => "Activities",
+        Group::Objects => "Objects",
+        Group::Symbols => "Symbols",
+        Group::Flags => "Flags",
+    }
+}
@@ -0,0 +1,251 @@
+use super::{
+    emoji_data,
+    images::{Avatar, ImageLoader},
+    markdown,
+};
+use crate::backend::Cache;
+use gtk::{
+    BoxExt, ButtonExt, ContainerExt, EntryExt, FlowBoxChildExt, FlowBoxExt, IsA, LabelExt,
+    Orientation, PopoverExt, ScrolledWindowExt, SearchEntryExt, WidgetExt,
+};
+use serenity::model::{channel::ReactionType, id::EmojiId};
+use std::{cell::RefCell, rc::Rc};
+
+const EMOJI_PER_ROW: u32 = 8;
+const CUSTOM_EMOJI_SIZE: f64 = 24.0;
+const PICKER_WIDTH: i32 = 360;
+const PICKER_HEIGHT: i32 = 400;
+
+/// A popover to pick an emoji from, listing the most frequently used ones, the custom
+/// emoji of every guild starting with the current one and the unicode emoji by category.
+/// Typing filters them by shortcode, it's destroyed once closed
+pub struct EmojiPicker {
+    popover: gtk::Popover,
+}
+
+/// A titled group of emoji, each with its shortcode
+struct Section {
+    header: gtk::Label,
+    grid: gtk::FlowBox,
+    emoji: Rc<Vec<(String, ReactionType)>>,
+}
+
+impl EmojiPicker {
+    pub fn new<W: IsA<gtk::Widget>>(
+        relative_to: &W,
+        frequent: &[ReactionType],
+        cache: &Cache,
+        guild_id: Option<u64>,
+        images: &mut ImageLoader,
+        on_pick: impl Fn(ReactionType) + 'static,
+    ) -> Self {
+        let popover = gtk::Popover::new(Some(relative_to));
+        let on_pick: Rc<dyn Fn(ReactionType)> = Rc::new(on_pick);
+        let query = Rc::new(RefCell::new(String::new()));
+
+        // An emoji without a shortcode is still shown, it only can't be searched for
+        let frequent = frequent
+            .iter()
+            .map(|emoji| (shortcode(emoji).unwrap_or_default(), emoji.clone()))
+            .collect();
+        let mut groups = vec![("Frequently Used".to_string(), frequent)];
+        groups.extend(custom_emoji(cache, guild_id));
+        groups.extend(emoji_data::categories().map(|(category, emoji)| {
+            let emoji = emoji
+                .into_iter()
+                .map(|(shortcode, unicode)| (shortcode, ReactionType::Unicode(unicode.to_string())))
+                .collect();
+
+            (category.to_string(), emoji)
+        }));
+
+        let sections_box = gtk::Box::new(Orientation::Vertical, 6);
+        let sections = groups
+            .into_iter()
+            .filter(|(_, emoji)| !emoji.is_empty())
+            .map(|(title, emoji)| {
+                let section = section(&title, emoji, &query, images, &popover, &on_pick);
+                sections_box.pack_start(&section.header, false, false, 0);
+                sections_box.pack_start(&section.grid, false, false, 0);
+
+                section
+            })
+            .collect::<Vec<_>>();
+        let sections = Rc::new(sections);
+
+        let search = gtk::SearchEntry::new();
+        search.set_placeholder_text(Some("Search emoji"));
+
+        let (search_query, search_sections) = (Rc::clone(&query), Rc::clone(&sections));
+        search.connect_search_changed(move |search| {
+            let text = search.get_text().map(|text| text.to_lowercase());
+            *search_query.borrow_mut() = text.unwrap_or_default();
+
+            // Sections without any match are hidden along with their header
+            let query = search_query.borrow();
+            for section in search_sections.iter() {
+                let visible = section
+                    .emoji
+                    .iter()
+                    .any(|(shortcode, _)| shortcode.contains(&*query));
+                section.header.set_visible(visible);
+                section.grid.set_visible(visible);
+                section.grid.invalidate_filter();
+            }
+        });
+
+        // Enter picks the first match
+        let (activate_popover, activate_pick) = (popover.clone(), Rc::clone(&on_pick));
+        search.connect_activate(move |_| {
+            let query = query.borrow();
+            let first = sections
+                .iter()
+                .flat_map(|section| section.emoji.iter())
+                .find(|(shortcode, _)| shortcode.contains(&*query));
+            if let Some((_, emoji)) = first {
+                activate_pick(emoji.clone());
+                activate_popover.hide();
+            }
+        });
+
+        let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled.set_size_request(PICKER_WIDTH, PICKER_HEIGHT);
+        scrolled.add(&sections_box);
+
+        let container = gtk::Box::new(Orientation::Vertical, 6);
+        container.pack_start(&search, false, false, 0);
+        container.pack_start(&scrolled, true, true, 0);
+
+        popover.add(&container);
+        popover.connect_closed(|popover| popover.destroy());
+
+        Self { popover }
+    }
+
+    pub fn show(&self) {
+        self.popover.show_all();
+    }
+}
+
+fn section(
+    title: &str,
+    emoji: Vec<(String, ReactionType)>,
+    query: &Rc<RefCell<String>>,
+    images: &mut ImageLoader,
+    popover: &gtk::Popover,
+    on_pick: &Rc<dyn Fn(ReactionType)>,
+) -> Section {
+    let header = gtk::Label::new(None);
+    header.set_markup(&format!("<b>{}</b>", glib::markup_escape_text(title)));
+    header.set_xalign(0.0);
+
+    let grid = gtk::FlowBox::new();
+    grid.set_selection_mode(gtk::SelectionMode::None);
+    grid.set_min_children_per_line(EMOJI_PER_ROW);
+    grid.set_max_children_per_line(EMOJI_PER_ROW);
+
+    for (shortcode, emoji) in emoji.iter() {
+        let button = gtk::Button::new();
+        button.set_relief(gtk::ReliefStyle::None);
+        if !shortcode.is_empty() {
+            button.set_tooltip_text(Some(&format!(":{}:", shortcode)));
+        }
+        match emoji {
+            ReactionType::Custom { animated, id, .. } => {
+                let image = Avatar::square(shortcode, CUSTOM_EMOJI_SIZE);
+                images.load(markdown::emoji_url(id.0, *animated), &image);
+                button.add(image.widget());
+            }
+            ReactionType::Unicode(unicode) => button.set_label(unicode),
+            _ => {}
+        }
+
+        let (on_pick, popover, emoji) = (Rc::clone(on_pick), popover.clone(), emoji.clone());
+        button.connect_clicked(move |_| {
+            on_pick(emoji.clone());
+            popover.hide();
+        });
+        grid.add(&button);
+    }
+
+    let emoji = Rc::new(emoji);
+    let (filter_query, filter_emoji) = (Rc::clone(query), Rc::clone(&emoji));
+    grid.set_filter_func(Some(Box::new(move |child| {
+        filter_emoji
+            .get(child.get_index() as usize)
+            .map_or(false, |(shortcode, _)| {
+                shortcode.contains(&*filter_query.borrow())
+            })
+    })));
+
+    Section {
+        header,
+        grid,
+        emoji,
+    }
+}
+
+/// The custom emoji of every guild with any, by guild name and starting with the current
+/// guild, each emoji along with its name
+pub fn custom_emoji(
+    cache: &Cache,
+    guild_id: Option<u64>,
+) -> Vec<(String, Vec<(String, ReactionType)>)> {
+    let mut guilds = cache
+        .guild_ids()
+        .into_iter()
+        .filter_map(|id| {
+            let guild = cache.guild(id)?;
+            let mut emoji = guild
+                .emojis
+                .iter()
+                .map(|(emoji_id, emoji)| {
+                    let reaction = ReactionType::Custom {
+                        animated: emoji.animated,
+                        id: EmojiId(*emoji_id),
+                        name: Some(emoji.name.clone()),
+                    };
+
+                    (emoji.name.clone(), reaction)
+                })
+                .collect::<Vec<_>>();
+            emoji.sort_by(|(a, _), (b, _)| a.to_lowercase().cmp(&b.to_lowercase()));
+
+            Some((Some(id) != guild_id, guild.name.clone(), emoji))
+        })
+        .filter(|(_, _, emoji)| !emoji.is_empty())
+        .collect::<Vec<_>>();
+    guilds.sort_by(|(a_other, a_name, _), (b_other, b_name, _)| {
+        a_other
+            .cmp(b_other)
+            .then_with(|| a_name.to_lowercase().cmp(&b_name.to_lowercase()))
+    });
+
+    guilds
+        .into_iter()
+        .map(|(_, name, emoji)| (name, emoji))
+        .collect()
+}
+
+/// How an emoji is written in a message
+pub fn message_text(emoji: &ReactionType) -> String {
+    match emoji {
+        ReactionType::Custom { animated, id, name } => format!(
+            "<{}:{}:{}>",
+            if *animated { "a" } else { "" },
+            name.as_ref().map(String::as_str).unwrap_or_default(),
+            id.0
+        ),
+        ReactionType::Unicode(unicode) => unicode.clone(),
+        _ => String::new(),
+    }
+}
+
+fn shortcode(emoji: &ReactionType) -> Option<String> {
+    match emoji {
+        ReactionType::Custom { name, .. } => name.clone(),
+        ReactionType::Unicode(unicode) => emoji_data::shortcode(unicode),
+        _ => None,
+    }
+}
@@ -0,0 +1,300 @@
+use std::{convert::TryFrom, io::Cursor, ptr::NonNull, slice};
+
+/// An image decoded by the image pipeline, animations carry the delay of each frame in
+/// milliseconds
+pub enum DecodedImage {
+    Still(DecodedImageData),
+    Animated(Vec<(DecodedImageData, u32)>),
+}
+
+/// Decoded 8-bit RGB or RGBA pixels, owning their buffer whether libwebp or Rust
+/// allocated it
+pub struct DecodedImageData {
+    buffer: PixelBuffer,
+    width: i32,
+    height: i32,
+    stride: i32,
+    has_alpha: bool,
+}
+
+impl DecodedImageData {
+    const RGBA_BYTES_PER_PX: i32 = 4;
+
+    fn from_rgba(buffer: PixelBuffer, width: i32, height: i32) -> Self {
+        let stride = width * Self::RGBA_BYTES_PER_PX;
+        assert!(buffer.as_ref().len() >= (stride * height) as usize);
+
+        Self {
+            buffer,
+            width,
+            height,
+            stride,
+            has_alpha: true,
+        }
+    }
+
+    /// The length in bytes of RGBA pixels of a size, unless glib can't take rows that
+    /// long or the pixels wouldn't fit in memory
+    fn rgba_len(width: u32, height: u32) -> Option<usize> {
+        let stride = i32::try_from(width)
+            .ok()?
+            .checked_mul(Self::RGBA_BYTES_PER_PX)?;
+        i32::try_from(height).ok()?;
+
+        (stride as usize).checked_mul(height as usize)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The length of a row of pixels in bytes
+    pub fn stride(&self) -> i32 {
+        self.stride
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    /// Hands the pixels over to glib without copying them, the buffer is freed once
+    /// glib is done with it
+    pub fn into_bytes(self) -> glib::Bytes {
+        glib::Bytes::from_owned(self.buffer)
+    }
+
+    pub fn into_pixbuf(self) -> gdk_pixbuf::Pixbuf {
+        let Self {
+            width,
+            height,
+            stride,
+            has_alpha,
+            ..
+        } = self;
+
+        gdk_pixbuf::Pixbuf::new_from_bytes(
+            &self.into_bytes(),
+            gdk_pixbuf::Colorspace::Rgb,
+            has_alpha,
+            8,
+            width,
+            height,
+            stride,
+        )
+    }
+}
+
+enum PixelBuffer {
+    Webp(WebpBuffer),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for PixelBuffer {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PixelBuffer::Webp(buffer) => buffer.as_ref(),
+            PixelBuffer::Owned(buffer) => buffer,
+        }
+    }
+}
+
+/// A buffer allocated by libwebp, freed with `WebPFree` once dropped
+struct WebpBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The buffer is uniquely owned and libwebp's allocator can free it from any thread
+unsafe impl Send for WebpBuffer {}
+
+impl AsRef<[u8]> for WebpBuffer {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for WebpBuffer {
+    fn drop(&mut self) {
+        unsafe { libwebp_sys::WebPFree(self.ptr.as_ptr() as _) };
+    }
+}
+
+/// Decodes PNG, JPEG, GIF and WebP images, sniffing the format from the data itself
+pub fn decode(input_bytes: &[u8]) -> Option<DecodedImage> {
+    use image::{AnimationDecoder, ImageFormat};
+
+    let format = match image::guess_format(input_bytes) {
+        Ok(format) => format,
+        Err(err) => {
+            eprintln!("Image Format Error: {:?}", err);
+            return None;
+        }
+    };
+
+    let decoded = match format {
+        ImageFormat::WEBP if is_animated_webp(input_bytes) => {
+            return decode_animated_webp(input_bytes).map(DecodedImage::Animated);
+        }
+        ImageFormat::WEBP => return decode_webp(input_bytes).map(DecodedImage::Still),
+        ImageFormat::GIF => image::gif::Decoder::new(Cursor::new(input_bytes))
+            .and_then(|decoder| decoder.into_frames().collect_frames())
+            .map(|frames| {
+                let mut frames = frames
+                    .into_iter()
+                    .map(|frame| {
+                        let delay = u32::from(frame.delay().to_integer());
+                        let buffer = frame.into_buffer();
+                        let (width, height) = (buffer.width() as i32, buffer.height() as i32);
+
+                        (
+                            DecodedImageData::from_rgba(
+                                PixelBuffer::Owned(buffer.into_raw()),
+                                width,
+                                height,
+                            ),
+                            delay,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                if frames.len() == 1 {
+                    DecodedImage::Still(frames.remove(0).0)
+                } else {
+                    DecodedImage::Animated(frames)
+                }
+            }),
+        ImageFormat::PNG | ImageFormat::JPEG => {
+            image::load_from_memory_with_format(input_bytes, format).map(|image| {
+                let buffer = image.to_rgba();
+                let (width, height) = (buffer.width() as i32, buffer.height() as i32);
+
+                DecodedImage::Still(DecodedImageData::from_rgba(
+                    PixelBuffer::Owned(buffer.into_raw()),
+                    width,
+                    height,
+                ))
+            })
+        }
+        format => {
+            eprintln!("Unsupported Image Format: {:?}", format);
+            return None;
+        }
+    };
+
+    match decoded {
+        Ok(DecodedImage::Animated(ref frames)) if frames.is_empty() => {
+            eprintln!("Image Decode Error: No frames");
+            None
+        }
+        Ok(decoded) => Some(decoded),
+        Err(err) => {
+            eprintln!("Image Decode Error: {:?}", err);
+            None
+        }
+    }
+}
+
+fn decode_webp(input_bytes: &[u8]) -> Option<DecodedImageData> {
+    let mut width = 0;
+    let mut height = 0;
+
+    let decoded = unsafe {
+        libwebp_sys::WebPDecodeRGBA(
+            input_bytes.as_ptr(),
+            input_bytes.len(),
+            &mut width,
+            &mut height,
+        )
+    };
+
+    let ptr = NonNull::new(decoded)?;
+    let len = match DecodedImageData::rgba_len(width as u32, height as u32) {
+        Some(len) => len,
+        None => {
+            unsafe { libwebp_sys::WebPFree(decoded as _) };
+            return None;
+        }
+    };
+
+    Some(DecodedImageData::from_rgba(
+        PixelBuffer::Webp(WebpBuffer { ptr, len }),
+        width,
+        height,
+    ))
+}
+
+fn is_animated_webp(input_bytes: &[u8]) -> bool {
+    let mut features = unsafe { std::mem::zeroed::<libwebp_sys::WebPBitstreamFeatures>() };
+    let status = unsafe {
+        libwebp_sys::WebPGetFeatures(input_bytes.as_ptr(), input_bytes.len(), &mut features)
+    };
+
+    status == libwebp_sys::VP8StatusCode::VP8_STATUS_OK && features.has_animation != 0
+}
+
+fn decode_animated_webp(input_bytes: &[u8]) -> Option<Vec<(DecodedImageData, u32)>> {
+    let data = libwebp_sys::WebPData {
+        bytes: input_bytes.as_ptr(),
+        size: input_bytes.len(),
+    };
+
+    unsafe {
+        let mut options = std::mem::zeroed::<libwebp_sys::WebPAnimDecoderOptions>();
+        if libwebp_sys::WebPAnimDecoderOptionsInit(&mut options) == 0 {
+            return None;
+        }
+        options.color_mode = libwebp_sys::WEBP_CSP_MODE::MODE_RGBA;
+
+        let decoder = libwebp_sys::WebPAnimDecoderNew(&data, &options);
+        if decoder.is_null() {
+            return None;
+        }
+
+        let mut info = std::mem::zeroed::<libwebp_sys::WebPAnimInfo>();
+        if libwebp_sys::WebPAnimDecoderGetInfo(decoder, &mut info) == 0 {
+            libwebp_sys::WebPAnimDecoderDelete(decoder);
+            return None;
+        }
+
+        // The canvas size comes from the file, so it may be anything
+        let len = match DecodedImageData::rgba_len(info.canvas_width, info.canvas_height) {
+            Some(len) => len,
+            None => {
+                libwebp_sys::WebPAnimDecoderDelete(decoder);
+                return None;
+            }
+        };
+        let (width, height) = (info.canvas_width as i32, info.canvas_height as i32);
+        let mut frames = Vec::with_capacity(info.frame_count as usize);
+        let mut previous_timestamp = 0;
+
+        while libwebp_sys::WebPAnimDecoderHasMoreFrames(decoder) != 0 {
+            let mut buffer = std::ptr::null_mut();
+            let mut timestamp = 0;
+            if libwebp_sys::WebPAnimDecoderGetNext(decoder, &mut buffer, &mut timestamp) == 0 {
+                break;
+            }
+
+            // The decoder reuses its buffer for every frame, so each one has to be copied
+            let pixels = slice::from_raw_parts(buffer, len).to_vec();
+            frames.push((
+                DecodedImageData::from_rgba(PixelBuffer::Owned(pixels), width, height),
+                (timestamp - previous_timestamp).max(0) as u32,
+            ));
+            previous_timestamp = timestamp;
+        }
+
+        libwebp_sys::WebPAnimDecoderDelete(decoder);
+
+        // Without a single frame there's nothing to show, nor to play
+        if frames.is_empty() {
+            return None;
+        }
+
+        Some(frames)
+    }
+}
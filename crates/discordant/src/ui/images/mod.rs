@@ -0,0 +1,397 @@
+mod decode;
+
+pub use decode::{decode, DecodedImage, DecodedImageData};
+
+use crate::backend::ImageRequest;
+use futures::channel::mpsc::UnboundedSender;
+use gdk::prelude::ContextExt;
+use glib::ObjectExt;
+use gtk::WidgetExt;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+/// How many bytes of decoded pixels are kept in memory, counting every frame of
+/// animations
+const CACHE_CAPACITY: usize = 256 * 1024 * 1024;
+
+/// Background colours for placeholders, picked by hashing the name they're for
+const PLACEHOLDER_COLORS: [(f64, f64, f64); 6] = [
+    (0.45, 0.54, 0.85),
+    (0.46, 0.49, 0.54),
+    (0.26, 0.71, 0.51),
+    (0.98, 0.65, 0.10),
+    (0.94, 0.28, 0.28),
+    (0.80, 0.36, 0.66),
+];
+
+/// Loads images through the backend's image pipeline without blocking the UI, every
+/// request is tagged with an id so the decoded image reaches the widgets waiting on it
+pub struct ImageLoader {
+    url_sender: UnboundedSender<ImageRequest>,
+    next_id: u64,
+    in_flight: HashMap<String, u64>,
+    waiting: HashMap<u64, (String, Vec<Avatar>)>,
+    cache: LruCache,
+}
+
+impl ImageLoader {
+    pub fn new(url_sender: UnboundedSender<ImageRequest>) -> Self {
+        Self {
+            url_sender,
+            next_id: 0,
+            in_flight: HashMap::new(),
+            waiting: HashMap::new(),
+            cache: LruCache::new(CACHE_CAPACITY),
+        }
+    }
+
+    /// Shows the image at the url in the avatar, either right away if it's cached or
+    /// once the backend delivers it
+    pub fn load(&mut self, url: String, avatar: &Avatar) {
+        if let Some(image) = self.cache.get(&url) {
+            avatar.set_image(&image);
+            return;
+        }
+
+        if let Some(id) = self.in_flight.get(&url) {
+            if let Some((_, avatars)) = self.waiting.get_mut(id) {
+                avatars.push(avatar.clone());
+            }
+            return;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if let Err(err) = self.url_sender.unbounded_send(ImageRequest {
+            id,
+            url: url.clone(),
+        }) {
+            eprintln!("URL Send Error: {:?}", err);
+            return;
+        }
+
+        self.in_flight.insert(url.clone(), id);
+        self.waiting.insert(id, (url, vec![avatar.clone()]));
+    }
+
+    /// The image at the url if it's loaded already, the first frame of animations
+    pub fn cached(&mut self, url: &str) -> Option<gdk_pixbuf::Pixbuf> {
+        match self.cache.get(url)? {
+            Image::Still(pixbuf) => Some(pixbuf),
+            Image::Animated(frames) => frames.first().map(|(pixbuf, _)| pixbuf.clone()),
+        }
+    }
+
+    /// Hands a decoded image to everything that requested it, failed requests leave
+    /// their placeholders in place
+    pub fn loaded(&mut self, id: u64, image: Option<DecodedImage>) {
+        let (url, avatars) = match self.waiting.remove(&id) {
+            Some(waiting) => waiting,
+            None => return,
+        };
+        self.in_flight.remove(&url);
+
+        if let Some(image) = image.map(Image::from_decoded) {
+            for avatar in &avatars {
+                avatar.set_image(&image);
+            }
+
+            self.cache.insert(url, image);
+        }
+    }
+}
+
+/// A least recently used cache of decoded images keyed by their url, limited by the
+/// size of their pixels rather than their number
+struct LruCache {
+    capacity: usize,
+    size: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, Image)>,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, url: &str) -> Option<Image> {
+        self.tick += 1;
+
+        let tick = self.tick;
+        self.entries.get_mut(url).map(|(last_used, image)| {
+            *last_used = tick;
+            image.clone()
+        })
+    }
+
+    /// Evicts the least recently used images until the image fits, images larger than
+    /// the whole cache aren't kept at all
+    fn insert(&mut self, url: String, image: Image) {
+        self.tick += 1;
+
+        if let Some((_, replaced)) = self.entries.remove(&url) {
+            self.size -= replaced.byte_size();
+        }
+
+        let size = image.byte_size();
+        if size > self.capacity {
+            return;
+        }
+
+        while self.size + size > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(url, _)| url.clone());
+
+            match oldest.and_then(|oldest| self.entries.remove(&oldest)) {
+                Some((_, evicted)) => self.size -= evicted.byte_size(),
+                None => break,
+            }
+        }
+
+        self.size += size;
+        self.entries.insert(url, (self.tick, image));
+    }
+}
+
+/// An image ready to be displayed, animations carry the delay of each frame in
+/// milliseconds
+#[derive(Clone)]
+pub enum Image {
+    Still(gdk_pixbuf::Pixbuf),
+    Animated(Rc<Vec<(gdk_pixbuf::Pixbuf, u32)>>),
+}
+
+impl Image {
+    fn from_decoded(image: DecodedImage) -> Self {
+        match image {
+            DecodedImage::Still(image_data) => Image::Still(image_data.into_pixbuf()),
+            DecodedImage::Animated(frames) => {
+                let frames = frames
+                    .into_iter()
+                    .map(|(image_data, delay)| (image_data.into_pixbuf(), delay))
+                    .collect();
+
+                Image::Animated(Rc::new(frames))
+            }
+        }
+    }
+
+    /// How much memory the pixels of all frames take up
+    fn byte_size(&self) -> usize {
+        let byte_size = |pixbuf: &gdk_pixbuf::Pixbuf| {
+            pixbuf.get_rowstride() as usize * pixbuf.get_height() as usize
+        };
+
+        match self {
+            Image::Still(pixbuf) => byte_size(pixbuf),
+            Image::Animated(frames) => frames.iter().map(|(pixbuf, _)| byte_size(pixbuf)).sum(),
+        }
+    }
+
+    fn scale(&self, width: i32, height: i32) -> Option<Self> {
+        let scale = |pixbuf: &gdk_pixbuf::Pixbuf| {
+            pixbuf.scale_simple(width, height, gdk_pixbuf::InterpType::Bilinear)
+        };
+
+        match self {
+            Image::Still(pixbuf) => scale(pixbuf).map(Image::Still),
+            Image::Animated(frames) => frames
+                .iter()
+                .map(|(pixbuf, delay)| scale(pixbuf).map(|pixbuf| (pixbuf, *delay)))
+                .collect::<Option<Vec<_>>>()
+                .map(|frames| Image::Animated(Rc::new(frames))),
+        }
+    }
+}
+
+/// A round or rectangular image which shows the initials of its name on a coloured
+/// background until the actual image is set
+#[derive(Clone)]
+pub struct Avatar {
+    area: gtk::DrawingArea,
+    image: Rc<RefCell<Option<gdk_pixbuf::Pixbuf>>>,
+    generation: Rc<Cell<u64>>,
+    zoom: Rc<Cell<f64>>,
+    width: f64,
+    height: f64,
+}
+
+impl Avatar {
+    pub fn new(name: &str, radius: f64) -> Self {
+        Self::with_shape(name, radius * 2.0, radius * 2.0, true)
+    }
+
+    /// A square image, like the ones of custom emoji
+    pub fn square(name: &str, size: f64) -> Self {
+        Self::with_shape(name, size, size, false)
+    }
+
+    /// A rectangular image, like the ones of embeds and attachments
+    pub fn sized(name: &str, width: f64, height: f64) -> Self {
+        Self::with_shape(name, width, height, false)
+    }
+
+    fn with_shape(name: &str, width: f64, height: f64, round: bool) -> Self {
+        let area = gtk::DrawingArea::new();
+        area.set_size_request(width as _, height as _);
+
+        let image: Rc<RefCell<Option<gdk_pixbuf::Pixbuf>>> = Rc::new(RefCell::new(None));
+        let initials = initials(name);
+        let (red, green, blue) =
+            PLACEHOLDER_COLORS[(hash(name) % PLACEHOLDER_COLORS.len() as u64) as usize];
+
+        let zoom = Rc::new(Cell::new(1.0));
+
+        let (draw_image, draw_zoom) = (Rc::clone(&image), Rc::clone(&zoom));
+        area.connect_draw(move |_, g| {
+            g.scale(draw_zoom.get(), draw_zoom.get());
+
+            if round {
+                let radius = width / 2.0;
+                g.arc(radius, radius, radius, 0.0, 2.0 * std::f64::consts::PI);
+            } else {
+                g.rectangle(0.0, 0.0, width, height);
+            }
+            g.clip();
+
+            if let Some(pixbuf) = &*draw_image.borrow() {
+                g.set_source_pixbuf(pixbuf, 0.0, 0.0);
+                g.paint();
+            } else {
+                g.set_source_rgb(red, green, blue);
+                g.paint();
+
+                g.set_source_rgb(1.0, 1.0, 1.0);
+                g.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+                g.set_font_size(width.min(height) * 0.4);
+
+                let extents = g.text_extents(&initials);
+                g.move_to(
+                    (width - extents.width) / 2.0 - extents.x_bearing,
+                    (height - extents.height) / 2.0 - extents.y_bearing,
+                );
+                g.show_text(&initials);
+            }
+
+            gtk::Inhibit(false)
+        });
+
+        Self {
+            area,
+            image,
+            generation: Rc::new(Cell::new(0)),
+            zoom,
+            width,
+            height,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::DrawingArea {
+        &self.area
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom.get()
+    }
+
+    /// Draws the image larger or smaller than its size, growing or shrinking the widget
+    /// along with it
+    pub fn set_zoom(&self, zoom: f64) {
+        self.zoom.set(zoom);
+        self.area.set_size_request(
+            (self.width * zoom).round() as _,
+            (self.height * zoom).round() as _,
+        );
+        self.area.queue_draw();
+    }
+
+    pub fn set_image(&self, image: &Image) {
+        // Stops any animation of a previously set image
+        self.generation.set(self.generation.get() + 1);
+
+        match image.scale(self.width as i32, self.height as i32) {
+            Some(Image::Still(pixbuf)) => {
+                *self.image.borrow_mut() = Some(pixbuf);
+                self.area.queue_draw();
+            }
+            Some(Image::Animated(frames)) => animate(
+                self.area.downgrade(),
+                Rc::clone(&self.image),
+                Rc::clone(&self.generation),
+                self.generation.get(),
+                frames,
+                0,
+            ),
+            None => {}
+        }
+    }
+}
+
+/// Shows a frame of an animation and schedules the next one, stopping once the widget
+/// is gone or another image was set
+fn animate(
+    area: glib::WeakRef<gtk::DrawingArea>,
+    image: Rc<RefCell<Option<gdk_pixbuf::Pixbuf>>>,
+    generation: Rc<Cell<u64>>,
+    current_generation: u64,
+    frames: Rc<Vec<(gdk_pixbuf::Pixbuf, u32)>>,
+    frame: usize,
+) {
+    /// Browsers play frames without a delay at this speed, so animations expect it
+    const DEFAULT_DELAY: u32 = 100;
+
+    let widget = match area.upgrade() {
+        Some(widget) if generation.get() == current_generation && !frames.is_empty() => widget,
+        _ => return,
+    };
+
+    let (pixbuf, delay) = &frames[frame % frames.len()];
+    *image.borrow_mut() = Some(pixbuf.clone());
+    widget.queue_draw();
+
+    let delay = if *delay <= 10 { DEFAULT_DELAY } else { *delay };
+    gtk::timeout_add(delay, move || {
+        animate(
+            area.clone(),
+            Rc::clone(&image),
+            Rc::clone(&generation),
+            current_generation,
+            Rc::clone(&frames),
+            frame + 1,
+        );
+
+        glib::Continue(false)
+    });
+}
+
+/// The first letters of up to the first two words of a name
+fn initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// A stable 64-bit FNV-1a hash, used wherever a hash has to survive restarts
+pub fn hash(input: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    input.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,145 @@
+use crate::backend::{self, Account};
+use gtk::{BoxExt, DialogExt, EntryExt, GtkWindowExt, LabelExt, WidgetExt};
+
+const LOGIN_WIDTH: i32 = 400;
+
+/// The accounts to log in to, the one to show first first. The account of
+/// `DISCORD_TOKEN` is shown first if it's set, otherwise an account is asked for if none
+/// is stored or Discord no longer accepts their tokens. Empty if logging in was given
+/// up on
+pub fn accounts() -> Vec<Account> {
+    let mut accounts = backend::load_accounts();
+    if let Ok(token) = std::env::var("DISCORD_TOKEN") {
+        // Listed along with the stored accounts, so storing the accounts again keeps them
+        let account = match accounts.iter().position(|account| account.token == token) {
+            Some(index) => accounts.remove(index),
+            None => {
+                let mut account = Account::new(backend::TEMPORARY_ID, token);
+                account.temporary = true;
+                account
+            }
+        };
+        accounts.insert(0, account);
+        return accounts;
+    }
+
+    // Connecting with a revoked token would wait for the connection to be ready forever
+    let mut error = None;
+    while let Some(account) = accounts.first() {
+        if !backend::token_revoked(&account.token) {
+            return accounts;
+        }
+
+        error = Some(match account.name.as_str() {
+            "" => "You were logged out, log in again".to_string(),
+            name => format!("{} was logged out, log in again", name),
+        });
+        backend::clear_store(account.id);
+        accounts.remove(0);
+        if accounts.is_empty() {
+            backend::forget_accounts();
+        } else if let Err(err) = backend::save_accounts(&accounts) {
+            eprintln!("Credentials Error: {}", err);
+        }
+    }
+
+    match ask_account(None, &[], error) {
+        Some(account) => {
+            let accounts = vec![account];
+            if let Err(err) = backend::save_accounts(&accounts) {
+                eprintln!("Credentials Error: {}", err);
+            }
+            accounts
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Asks for the token of an account until Discord accepts one which isn't among the
+/// accounts we're logged in to already, first showing why we're asking if given
+pub fn ask_account(
+    parent: Option<&gtk::Window>,
+    existing: &[Account],
+    mut error: Option<String>,
+) -> Option<Account> {
+    loop {
+        let token = ask_token(parent, error.as_ref().map(String::as_str))?;
+        let user = match backend::check_token(&token) {
+            Ok(user) => user,
+            Err(err) => {
+                error = Some(err);
+                continue;
+            }
+        };
+
+        if existing.iter().any(|account| account.user_id == user.id.0) {
+            error = Some(format!("You're already logged in as {}", user.name));
+            continue;
+        }
+
+        let id = existing
+            .iter()
+            .filter(|account| !account.temporary)
+            .map(|account| account.id + 1)
+            .max()
+            .unwrap_or(0);
+        let mut account = Account::new(id, token);
+        account.user_id = user.id.0;
+        account.name = user.name;
+        return Some(account);
+    }
+}
+
+/// Asks for a token, showing why the previous one wasn't accepted
+fn ask_token(parent: Option<&gtk::Window>, error: Option<&str>) -> Option<String> {
+    // Without a window yet there's nothing to go back to
+    let cancel = if parent.is_some() { "_Cancel" } else { "_Quit" };
+    let dialog = gtk::Dialog::new_with_buttons(
+        Some("Log In to Discord"),
+        parent,
+        gtk::DialogFlags::MODAL,
+        &[
+            (cancel, gtk::ResponseType::Cancel),
+            ("_Log In", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_default_size(LOGIN_WIDTH, -1);
+
+    let help = gtk::Label::new(Some("Enter the token of your Discord account"));
+    help.set_xalign(0.0);
+    help.set_line_wrap(true);
+
+    let entry = gtk::Entry::new();
+    entry.set_visibility(false);
+    entry.set_placeholder_text(Some("Token"));
+    entry.set_activates_default(true);
+
+    let content = dialog.get_content_area();
+    content.set_spacing(6);
+    content.pack_start(&help, false, false, 0);
+    content.pack_start(&entry, false, false, 0);
+
+    if let Some(error) = error {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!(
+            "<span foreground=\"#f04747\">{}</span>",
+            glib::markup_escape_text(error)
+        ));
+        label.set_xalign(0.0);
+        label.set_line_wrap(true);
+        content.pack_start(&label, false, false, 0);
+    }
+
+    dialog.show_all();
+
+    let response = dialog.run();
+    let token = entry.get_text().map(|token| token.trim().to_string());
+    dialog.destroy();
+
+    if response == gtk::ResponseType::Accept.into() {
+        Some(token.unwrap_or_default())
+    } else {
+        None
+    }
+}
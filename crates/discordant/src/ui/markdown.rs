@@ -0,0 +1,669 @@
+use super::{
+    code_block::CodeBlock,
+    images::{Avatar, ImageLoader},
+};
+use crate::backend::Cache;
+use gtk::{
+    BoxExt, ContainerExt, Inhibit, TextBufferExt, TextTagExt, TextTagTableExt, TextViewExt,
+    WidgetExt,
+};
+
+const EMOJI_SIZE: f64 = 22.0;
+/// Every spoiler gets its own tag named with this prefix, so each reveals on its own
+const SPOILER_PREFIX: &str = "spoiler-";
+
+/// A piece of message content as Discord's flavour of markdown structures it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Text(String),
+    Bold(Vec<Node>),
+    Italic(Vec<Node>),
+    Underline(Vec<Node>),
+    Strikethrough(Vec<Node>),
+    Spoiler(Vec<Node>),
+    InlineCode(String),
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    BlockQuote(Vec<Node>),
+    UserMention(u64),
+    ChannelMention(u64),
+    RoleMention(u64),
+    Emoji {
+        name: String,
+        id: u64,
+        animated: bool,
+    },
+}
+
+/// Parses message content, anything that isn't valid markup is kept as text
+pub fn parse(content: &str) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut rest = content;
+
+    // Code blocks take precedence over everything else, nothing inside them is markup
+    while let Some(start) = rest.find("```") {
+        let inner = &rest[start + 3..];
+        let end = match inner.find("```") {
+            Some(end) if !inner[..end].trim().is_empty() => end,
+            _ => break,
+        };
+
+        parse_blocks(&rest[..start], &mut nodes);
+        nodes.push(code_block(&inner[..end]));
+        rest = &inner[end + 3..];
+    }
+    parse_blocks(rest, &mut nodes);
+
+    nodes
+}
+
+/// A code block's first line names its language if it's the only word on it
+fn code_block(inner: &str) -> Node {
+    let (language, code) = match inner.find('\n') {
+        Some(newline)
+            if !inner[..newline].is_empty()
+                && inner[..newline]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-#_.".contains(c)) =>
+        {
+            (Some(inner[..newline].to_string()), &inner[newline + 1..])
+        }
+        _ => (None, inner.trim_start_matches('\n')),
+    };
+
+    Node::CodeBlock {
+        language,
+        code: code.trim_end_matches('\n').to_string(),
+    }
+}
+
+/// Splits text into block quotes and everything else, `> ` quotes a single line while
+/// `>>> ` quotes everything after it
+fn parse_blocks(text: &str, nodes: &mut Vec<Node>) {
+    let mut plain = String::new();
+    let mut quoted = String::new();
+
+    let flush_plain = |plain: &mut String, nodes: &mut Vec<Node>| {
+        if !plain.is_empty() {
+            nodes.extend(parse_inline(plain));
+            plain.clear();
+        }
+    };
+    let flush_quoted = |quoted: &mut String, nodes: &mut Vec<Node>| {
+        if !quoted.is_empty() {
+            nodes.push(Node::BlockQuote(parse_inline(
+                quoted.trim_end_matches('\n'),
+            )));
+            quoted.clear();
+        }
+    };
+
+    let mut lines = text.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let newline = if lines.peek().is_some() { "\n" } else { "" };
+
+        if line.starts_with(">>> ") {
+            flush_plain(&mut plain, nodes);
+            quoted.push_str(&line[4..]);
+            quoted.push_str(newline);
+            for line in lines.by_ref() {
+                quoted.push_str(line);
+                quoted.push('\n');
+            }
+        } else if line.starts_with("> ") {
+            flush_plain(&mut plain, nodes);
+            quoted.push_str(&line[2..]);
+            quoted.push_str(newline);
+        } else {
+            flush_quoted(&mut quoted, nodes);
+            plain.push_str(line);
+            plain.push_str(newline);
+        }
+    }
+
+    flush_quoted(&mut quoted, nodes);
+    flush_plain(&mut plain, nodes);
+}
+
+fn parse_inline(text: &str) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let consumed = text.len() - rest.len();
+        if let Some((node, len)) = inline_node(rest, text[..consumed].chars().next_back()) {
+            if !plain.is_empty() {
+                nodes.push(Node::Text(std::mem::replace(&mut plain, String::new())));
+            }
+            nodes.push(node);
+            rest = &rest[len..];
+            continue;
+        }
+
+        match rest[c.len_utf8()..].chars().next() {
+            Some(escaped) if c == '\\' && escaped.is_ascii_punctuation() => {
+                plain.push(escaped);
+                rest = &rest[c.len_utf8() + escaped.len_utf8()..];
+            }
+            _ => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        nodes.push(Node::Text(plain));
+    }
+
+    nodes
+}
+
+/// Parses the markup starting at the beginning of the text, returning it along with the
+/// length of text it spans
+fn inline_node(text: &str, preceding: Option<char>) -> Option<(Node, usize)> {
+    type Wrap = fn(Vec<Node>) -> Node;
+    const DELIMITERS: [(&str, Wrap); 6] = [
+        ("||", Node::Spoiler),
+        ("**", Node::Bold),
+        ("__", Node::Underline),
+        ("~~", Node::Strikethrough),
+        ("*", Node::Italic),
+        ("_", Node::Italic),
+    ];
+
+    if text.starts_with('`') {
+        let ticks = if text.starts_with("``") { "``" } else { "`" };
+        let inner = &text[ticks.len()..];
+        let end = inner.find(ticks).filter(|end| *end > 0)?;
+
+        return Some((
+            Node::InlineCode(inner[..end].to_string()),
+            end + ticks.len() * 2,
+        ));
+    }
+
+    if text.starts_with('<') {
+        return mention(text);
+    }
+
+    for (delimiter, wrap) in DELIMITERS.iter() {
+        if !text.starts_with(delimiter) {
+            continue;
+        }
+
+        // Underscores within words, like in snake_case, aren't markup
+        if *delimiter == "_" && preceding.map_or(false, char::is_alphanumeric) {
+            continue;
+        }
+
+        let inner = &text[delimiter.len()..];
+        if let Some(end) = closing(inner, delimiter) {
+            return Some((wrap(parse_inline(&inner[..end])), end + delimiter.len() * 2));
+        }
+    }
+
+    None
+}
+
+/// Finds where the delimiter closing the text is, if anywhere
+fn closing(text: &str, delimiter: &str) -> Option<usize> {
+    let marker = delimiter.chars().next()?;
+    if delimiter.len() == 1 && text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut chars = text.char_indices();
+    while let Some((position, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+            continue;
+        }
+
+        if position == 0 || !text[position..].starts_with(delimiter) {
+            continue;
+        }
+
+        let after = &text[position + delimiter.len()..];
+        if delimiter.len() == 1 {
+            // Italics may contain bold or underlined text, which uses doubled markers
+            if after.starts_with(marker) {
+                chars.next();
+                continue;
+            }
+
+            if text[..position].ends_with(char::is_whitespace)
+                || (marker == '_' && after.starts_with(char::is_alphanumeric))
+            {
+                continue;
+            }
+
+            return Some(position);
+        }
+
+        // A run of markers is closed by its last ones, so `***text***` closes the bold
+        // around the italics rather than inside them
+        let run = after.chars().take_while(|c| *c == marker).count();
+        return Some(position + run * marker.len_utf8());
+    }
+
+    None
+}
+
+/// Parses `<@id>`, `<@!id>`, `<@&id>`, `<#id>` and `<:name:id>` or `<a:name:id>`
+fn mention(text: &str) -> Option<(Node, usize)> {
+    let end = text.find('>')?;
+    let inner = &text[1..end];
+
+    let node = if inner.starts_with("@&") {
+        Node::RoleMention(inner[2..].parse().ok()?)
+    } else if inner.starts_with("@!") {
+        Node::UserMention(inner[2..].parse().ok()?)
+    } else if inner.starts_with('@') {
+        Node::UserMention(inner[1..].parse().ok()?)
+    } else if inner.starts_with('#') {
+        Node::ChannelMention(inner[1..].parse().ok()?)
+    } else {
+        let (animated, emoji) = if inner.starts_with("a:") {
+            (true, &inner[2..])
+        } else if inner.starts_with(':') {
+            (false, &inner[1..])
+        } else {
+            return None;
+        };
+
+        let separator = emoji.find(':')?;
+        let name = &emoji[..separator];
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+
+        Node::Emoji {
+            name: name.to_string(),
+            id: emoji[separator + 1..].parse().ok()?,
+            animated,
+        }
+    };
+
+    Some((node, end + 1))
+}
+
+/// Renders parsed content into a container, resolving mentions to names through the
+/// cache and loading custom emoji into the text. Text goes into text views and code
+/// blocks get widgets of their own between them
+pub fn render(
+    container: &gtk::Box,
+    nodes: &[Node],
+    cache: &Cache,
+    guild_id: Option<u64>,
+    images: &mut ImageLoader,
+) {
+    for child in container.get_children() {
+        container.remove(&child);
+    }
+
+    let mut text = Vec::new();
+    for node in nodes {
+        match node {
+            Node::CodeBlock { language, code } => {
+                if !text.is_empty() {
+                    let view = text_view(&text, cache, guild_id, images);
+                    container.pack_start(&view, false, false, 0);
+                    text.clear();
+                }
+
+                let code_block = CodeBlock::new(language.as_ref().map(String::as_str), code);
+                container.pack_start(code_block.widget(), false, false, 0);
+            }
+            node => text.push(node.clone()),
+        }
+    }
+
+    if !text.is_empty() {
+        let view = text_view(&text, cache, guild_id, images);
+        container.pack_start(&view, false, false, 0);
+    }
+
+    container.show_all();
+}
+
+fn text_view(
+    nodes: &[Node],
+    cache: &Cache,
+    guild_id: Option<u64>,
+    images: &mut ImageLoader,
+) -> gtk::TextView {
+    let view = gtk::TextView::new();
+    view.set_editable(false);
+    view.set_cursor_visible(false);
+    view.set_wrap_mode(gtk::WrapMode::WordChar);
+    connect_spoilers(&view);
+
+    let buffer = match view.get_buffer() {
+        Some(buffer) => buffer,
+        None => return view,
+    };
+    create_tags(&buffer);
+
+    let mut renderer = Renderer {
+        view: &view,
+        buffer: &buffer,
+        cache,
+        guild_id,
+        images,
+        spoilers: 0,
+    };
+    renderer.render(nodes, &mut Vec::new());
+
+    // Block quotes start and end their own lines, which shouldn't leave empty lines
+    // around the text
+    let mut start = buffer.get_start_iter();
+    if start.get_char() == Some('\n') {
+        let mut next = start.clone();
+        next.forward_char();
+        buffer.delete(&mut start, &mut next);
+    }
+    let mut end = buffer.get_end_iter();
+    if end.backward_char() && end.get_char() == Some('\n') {
+        buffer.delete(&mut end, &mut buffer.get_end_iter());
+    }
+
+    view
+}
+
+/// Reveals spoilers in a text view once they're clicked
+fn connect_spoilers(view: &gtk::TextView) {
+    view.connect_button_release_event(|view, event| {
+        let (x, y) = event.get_position();
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+
+        if let (Some(buffer), Some(iter)) = (view.get_buffer(), view.get_iter_at_location(x, y)) {
+            for tag in iter.get_tags() {
+                let is_spoiler = tag
+                    .get_property_name()
+                    .map_or(false, |name| name.starts_with(SPOILER_PREFIX));
+
+                if is_spoiler {
+                    buffer.remove_tag(&tag, &buffer.get_start_iter(), &buffer.get_end_iter());
+                }
+            }
+        }
+
+        Inhibit(false)
+    });
+}
+
+fn create_tags(buffer: &gtk::TextBuffer) {
+    let table = match buffer.get_tag_table() {
+        Some(table) => table,
+        None => return,
+    };
+
+    if table.lookup("bold").is_some() {
+        return;
+    }
+
+    let tag = |name: &str| {
+        let tag = gtk::TextTag::new(Some(name));
+        table.add(&tag);
+        tag
+    };
+
+    tag("bold").set_property_weight(700);
+    tag("italic").set_property_style(pango::Style::Italic);
+    tag("underline").set_property_underline(pango::Underline::Single);
+    tag("strikethrough").set_property_strikethrough(true);
+
+    let code = tag("code");
+    code.set_property_family(Some("monospace"));
+    code.set_property_background(Some("rgba(127, 127, 127, 0.2)"));
+
+    let quote = tag("quote");
+    quote.set_property_left_margin(12);
+    quote.set_property_foreground(Some("rgba(127, 127, 127, 1.0)"));
+
+    let mention = tag("mention");
+    mention.set_property_foreground(Some("#7289da"));
+    mention.set_property_background(Some("rgba(114, 137, 218, 0.15)"));
+}
+
+struct Renderer<'a> {
+    view: &'a gtk::TextView,
+    buffer: &'a gtk::TextBuffer,
+    cache: &'a Cache,
+    guild_id: Option<u64>,
+    images: &'a mut ImageLoader,
+    spoilers: usize,
+}
+
+impl<'a> Renderer<'a> {
+    fn render(&mut self, nodes: &[Node], tags: &mut Vec<gtk::TextTag>) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.insert(text, tags),
+                Node::Bold(children) => self.render_tagged(children, tags, "bold"),
+                Node::Italic(children) => self.render_tagged(children, tags, "italic"),
+                Node::Underline(children) => self.render_tagged(children, tags, "underline"),
+                Node::Strikethrough(children) => {
+                    self.render_tagged(children, tags, "strikethrough")
+                }
+                Node::Spoiler(children) => {
+                    let spoiler =
+                        gtk::TextTag::new(Some(&format!("{}{}", SPOILER_PREFIX, self.spoilers)));
+                    spoiler.set_property_background(Some("#202225"));
+                    spoiler.set_property_foreground(Some("#202225"));
+                    self.spoilers += 1;
+
+                    if let Some(table) = self.buffer.get_tag_table() {
+                        table.add(&spoiler);
+                    }
+
+                    tags.push(spoiler);
+                    self.render(children, tags);
+                    tags.pop();
+                }
+                Node::InlineCode(code) => self.insert_tagged(code, tags, "code"),
+                // Code blocks only occur at the top level, where `render` gives them
+                // widgets of their own
+                Node::CodeBlock { .. } => {}
+                Node::BlockQuote(children) => {
+                    self.start_block();
+                    self.render_tagged(children, tags, "quote");
+                    self.insert("\n", tags);
+                }
+                Node::UserMention(_) | Node::ChannelMention(_) | Node::RoleMention(_) => {
+                    let mention = mention_text(node, self.cache, self.guild_id);
+                    self.insert_tagged(&mention, tags, "mention");
+                }
+                Node::Emoji { name, id, animated } => {
+                    let anchor = self
+                        .buffer
+                        .create_child_anchor(&mut self.buffer.get_end_iter());
+
+                    if let Some(anchor) = anchor {
+                        let emoji = Avatar::square(name, EMOJI_SIZE);
+                        emoji
+                            .widget()
+                            .set_tooltip_text(Some(&format!(":{}:", name)));
+                        emoji.widget().show();
+                        self.images.load(emoji_url(*id, *animated), &emoji);
+
+                        self.view.add_child_at_anchor(emoji.widget(), &anchor);
+                    }
+                }
+            }
+        }
+    }
+
+    fn render_tagged(&mut self, nodes: &[Node], tags: &mut Vec<gtk::TextTag>, name: &str) {
+        match self.named_tag(name) {
+            Some(tag) => {
+                tags.push(tag);
+                self.render(nodes, tags);
+                tags.pop();
+            }
+            None => self.render(nodes, tags),
+        }
+    }
+
+    fn insert_tagged(&mut self, text: &str, tags: &mut Vec<gtk::TextTag>, name: &str) {
+        self.render_tagged(&[Node::Text(text.to_string())], tags, name);
+    }
+
+    fn insert(&self, text: &str, tags: &[gtk::TextTag]) {
+        let start = self.buffer.get_end_iter().get_offset();
+        self.buffer.insert(&mut self.buffer.get_end_iter(), text);
+
+        let (start, end) = (
+            self.buffer.get_iter_at_offset(start),
+            self.buffer.get_end_iter(),
+        );
+        for tag in tags {
+            self.buffer.apply_tag(tag, &start, &end);
+        }
+    }
+
+    /// Blocks always start on a line of their own
+    fn start_block(&self) {
+        let mut end = self.buffer.get_end_iter();
+        if end.backward_char() && end.get_char() != Some('\n') {
+            self.buffer.insert(&mut self.buffer.get_end_iter(), "\n");
+        }
+    }
+
+    fn named_tag(&self, name: &str) -> Option<gtk::TextTag> {
+        self.buffer.get_tag_table()?.lookup(name)
+    }
+}
+
+/// The content as plain text, for where markup can't be shown. Spoilers stay hidden
+pub fn plain_text(nodes: &[Node], cache: &Cache, guild_id: Option<u64>) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(plain) | Node::InlineCode(plain) => text.push_str(plain),
+            Node::Bold(children)
+            | Node::Italic(children)
+            | Node::Underline(children)
+            | Node::Strikethrough(children)
+            | Node::BlockQuote(children) => text.push_str(&plain_text(children, cache, guild_id)),
+            Node::Spoiler(_) => text.push_str("[spoiler]"),
+            Node::CodeBlock { code, .. } => text.push_str(code),
+            Node::UserMention(_) | Node::ChannelMention(_) | Node::RoleMention(_) => {
+                text.push_str(&mention_text(node, cache, guild_id))
+            }
+            Node::Emoji { name, .. } => text.push_str(&format!(":{}:", name)),
+        }
+    }
+
+    text
+}
+
+/// How a mention reads, members are named by their nickname in the message's guild
+fn mention_text(node: &Node, cache: &Cache, guild_id: Option<u64>) -> String {
+    match node {
+        Node::UserMention(user_id) => {
+            let name = cache
+                .display_name(guild_id, *user_id)
+                .unwrap_or_else(|| user_id.to_string());
+            format!("@{}", name)
+        }
+        Node::ChannelMention(channel_id) => {
+            let name = cache
+                .channel(*channel_id)
+                .map(|channel| channel.name)
+                .unwrap_or_else(|| "deleted-channel".to_string());
+            format!("#{}", name)
+        }
+        Node::RoleMention(role_id) => {
+            let name = guild_id
+                .and_then(|guild_id| cache.guild(guild_id))
+                .and_then(|guild| guild.roles.get(role_id).map(|role| role.name.clone()))
+                .unwrap_or_else(|| "deleted-role".to_string());
+            format!("@{}", name)
+        }
+        _ => String::new(),
+    }
+}
+
+pub fn emoji_url(id: u64, animated: bool) -> String {
+    format!(
+        "https://cdn.discordapp.com/emojis/{}.{}",
+        id,
+        if animated { "gif" } else { "png" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Node};
+
+    fn text(text: &str) -> Node {
+        Node::Text(text.to_string())
+    }
+
+    #[test]
+    fn unclosed_markers_stay_text() {
+        assert_eq!(parse("**bold"), vec![text("**bold")]);
+        assert_eq!(parse("a *b"), vec![text("a *b")]);
+        assert_eq!(parse("||hidden"), vec![text("||hidden")]);
+        assert_eq!(parse("`code"), vec![text("`code")]);
+        assert_eq!(parse("snake_case_name"), vec![text("snake_case_name")]);
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse("**__x__**"),
+            vec![Node::Bold(vec![Node::Underline(vec![text("x")])])]
+        );
+    }
+
+    #[test]
+    fn spoiler_containing_code() {
+        assert_eq!(
+            parse("||`code`||"),
+            vec![Node::Spoiler(vec![Node::InlineCode("code".to_string())])]
+        );
+    }
+
+    #[test]
+    fn fences() {
+        assert_eq!(
+            parse("```rust\nfn main() {}\n```"),
+            vec![Node::CodeBlock {
+                language: Some("rust".to_string()),
+                code: "fn main() {}".to_string(),
+            }]
+        );
+        // Without a closing line nothing is a code block, not even the rest
+        assert_eq!(
+            parse("```rust\nfn main() {}"),
+            vec![text("```rust\nfn main() {}")]
+        );
+    }
+
+    #[test]
+    fn mentions() {
+        assert_eq!(
+            parse("hi <@!123> in <#456> <:blob:789>"),
+            vec![
+                text("hi "),
+                Node::UserMention(123),
+                text(" in "),
+                Node::ChannelMention(456),
+                text(" "),
+                Node::Emoji {
+                    name: "blob".to_string(),
+                    id: 789,
+                    animated: false,
+                },
+            ]
+        );
+        assert_eq!(parse("<@&5>"), vec![Node::RoleMention(5)]);
+        assert_eq!(parse("<@abc>"), vec![text("<@abc>")]);
+    }
+}
@@ -0,0 +1,279 @@
+use super::{
+    images::{Avatar, ImageLoader},
+    presence,
+};
+use crate::backend::{Cache, GuildData};
+use gtk::{
+    AdjustmentExt, BoxExt, ContainerExt, LabelExt, Orientation, ScrolledWindowExt, StyleContextExt,
+    WidgetExt,
+};
+use serenity::model::user::OnlineStatus;
+use std::ops::Range;
+
+/// Every row is this tall, so which ones are in view follows from the scroll position
+const ROW_HEIGHT: i32 = 44;
+/// Rows built beyond those in view, so scrolling a little doesn't rebuild any
+const OVERSCAN: usize = 30;
+const AVATAR_RADIUS: f64 = 16.0;
+
+/// The members of the currently selected guild, grouped under their hoisted roles while
+/// online. Only the rows in view and around them are built, so the list stays
+/// responsive in guilds with many thousands of members
+pub struct MemberList {
+    container: gtk::ScrolledWindow,
+    rows: gtk::Box,
+    entries: Vec<Entry>,
+    /// The entries which currently have rows
+    built: Range<usize>,
+    guild_id: Option<u64>,
+}
+
+#[derive(PartialEq)]
+enum Entry {
+    /// A group's name and how many members are in it
+    Group(String, usize),
+    Member(MemberEntry),
+}
+
+#[derive(PartialEq)]
+struct MemberEntry {
+    name: String,
+    /// The colour of the member's highest coloured role
+    color: Option<(u8, u8, u8)>,
+    avatar: String,
+    status: OnlineStatus,
+    activity: Option<String>,
+}
+
+impl MemberList {
+    pub fn new() -> Self {
+        let rows = gtk::Box::new(Orientation::Vertical, 0);
+        rows.set_valign(gtk::Align::Start);
+
+        let container = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        container.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        container.add(&rows);
+
+        Self {
+            container,
+            rows,
+            entries: Vec::new(),
+            built: 0..0,
+            guild_id: None,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::ScrolledWindow {
+        &self.container
+    }
+
+    /// Lists the members of a guild, scrolling back up if it's another guild than before
+    pub fn set_members(
+        &mut self,
+        guild_id: u64,
+        guild: &GuildData,
+        cache: &Cache,
+        images: &mut ImageLoader,
+    ) {
+        let entries = entries(guild, cache);
+        if self.guild_id != Some(guild_id) {
+            self.guild_id = Some(guild_id);
+            if let Some(adjustment) = self.container.get_vadjustment() {
+                adjustment.set_value(0.0);
+            }
+        } else if entries.len() == self.entries.len()
+            && entries.get(self.built.clone()) == self.entries.get(self.built.clone())
+        {
+            // Nothing changed among the built rows, and the rest are only margins
+            self.entries = entries;
+            return;
+        }
+
+        self.entries = entries;
+        self.build(self.in_view(), images);
+    }
+
+    pub fn clear(&mut self) {
+        for child in self.rows.get_children() {
+            self.rows.remove(&child);
+        }
+
+        self.rows.set_margin_top(0);
+        self.rows.set_margin_bottom(0);
+        self.entries.clear();
+        self.built = 0..0;
+        self.guild_id = None;
+    }
+
+    /// Builds the rows which came into view, unless they're built already
+    pub fn scrolled(&mut self, images: &mut ImageLoader) {
+        let in_view = self.in_view();
+        if in_view.start < self.built.start || in_view.end > self.built.end {
+            self.build(in_view, images);
+        }
+    }
+
+    /// Whether the rows of the last members are built
+    pub fn at_end(&self) -> bool {
+        self.built.end == self.entries.len()
+    }
+
+    fn in_view(&self) -> Range<usize> {
+        let (top, height) = self
+            .container
+            .get_vadjustment()
+            .map_or((0.0, 0.0), |adjustment| {
+                (adjustment.get_value(), adjustment.get_page_size())
+            });
+
+        let first = (top / f64::from(ROW_HEIGHT)) as usize;
+        let last = ((top + height) / f64::from(ROW_HEIGHT)).ceil() as usize;
+        first.min(self.entries.len())..last.min(self.entries.len())
+    }
+
+    /// Replaces the built rows with those of the entries in view and around them. The
+    /// rows' margins stand in for the rest, keeping the list as tall as if all were built
+    fn build(&mut self, in_view: Range<usize>, images: &mut ImageLoader) {
+        for child in self.rows.get_children() {
+            self.rows.remove(&child);
+        }
+
+        let start = in_view.start.saturating_sub(OVERSCAN);
+        let end = (in_view.end + OVERSCAN).min(self.entries.len());
+        for entry in &self.entries[start..end] {
+            let row = match entry {
+                Entry::Group(name, count) => group_row(name, *count),
+                Entry::Member(member) => member_row(member, images),
+            };
+            row.set_size_request(-1, ROW_HEIGHT);
+            self.rows.pack_start(&row, false, false, 0);
+        }
+
+        self.rows.set_margin_top(start as i32 * ROW_HEIGHT);
+        self.rows
+            .set_margin_bottom((self.entries.len() - end) as i32 * ROW_HEIGHT);
+        self.rows.show_all();
+        self.built = start..end;
+    }
+}
+
+/// Online members go under their highest hoisted role, or else under "Online". Offline
+/// members all go under "Offline", whatever their roles
+fn entries(guild: &GuildData, cache: &Cache) -> Vec<Entry> {
+    let mut hoisted = guild
+        .roles
+        .iter()
+        .filter(|(_, role)| role.hoist)
+        .collect::<Vec<_>>();
+    hoisted.sort_by_key(|(_, role)| std::cmp::Reverse(role.position));
+
+    let (online_group, offline_group) = (hoisted.len(), hoisted.len() + 1);
+    let mut groups = (0..=offline_group).map(|_| Vec::new()).collect::<Vec<_>>();
+    for member in guild.members.values() {
+        let user = match cache.user(member.user_id) {
+            Some(user) => user,
+            None => continue,
+        };
+        let presence = cache.presence(member.user_id);
+        let status = presence
+            .as_ref()
+            .map_or(OnlineStatus::Offline, |presence| presence.status);
+
+        let color = member
+            .roles
+            .iter()
+            .filter_map(|role_id| guild.roles.get(role_id))
+            .filter(|role| role.color != (0, 0, 0))
+            .max_by_key(|role| role.position)
+            .map(|role| role.color);
+
+        let group = if presence::is_online(status) {
+            hoisted
+                .iter()
+                .position(|(role_id, _)| member.roles.contains(*role_id))
+                .unwrap_or(online_group)
+        } else {
+            offline_group
+        };
+
+        groups[group].push(MemberEntry {
+            name: member.nickname.as_ref().unwrap_or(&user.name).clone(),
+            color,
+            avatar: user.avatar.clone(),
+            status,
+            activity: presence.and_then(|presence| presence.activity),
+        });
+    }
+
+    let names = hoisted
+        .iter()
+        .map(|(_, role)| role.name.clone())
+        .chain(vec!["Online".to_string(), "Offline".to_string()]);
+
+    let mut entries = Vec::new();
+    for (name, mut members) in names.zip(groups) {
+        if members.is_empty() {
+            continue;
+        }
+
+        members.sort_by_cached_key(|member| member.name.to_lowercase());
+        entries.push(Entry::Group(name, members.len()));
+        entries.extend(members.into_iter().map(Entry::Member));
+    }
+
+    entries
+}
+
+fn group_row(name: &str, count: usize) -> gtk::Box {
+    let label = gtk::Label::new(Some(&format!("{} — {}", name.to_uppercase(), count)));
+    label.set_xalign(0.0);
+    label.set_valign(gtk::Align::End);
+    label.get_style_context().add_class("dim-label");
+
+    let row = gtk::Box::new(Orientation::Horizontal, 0);
+    row.pack_start(&label, true, true, 0);
+
+    row
+}
+
+fn member_row(member: &MemberEntry, images: &mut ImageLoader) -> gtk::Box {
+    let avatar = Avatar::new(&member.name, AVATAR_RADIUS);
+    images.load(member.avatar.clone(), &avatar);
+
+    let escaped = glib::markup_escape_text(&member.name);
+    let name_markup = match member.color {
+        Some((r, g, b)) => format!(
+            "<span foreground=\"#{:02x}{:02x}{:02x}\">{}</span>",
+            r, g, b, escaped
+        ),
+        None => escaped.to_string(),
+    };
+    let name = gtk::Label::new(None);
+    name.set_markup(&format!(
+        "{} {}",
+        presence::status_dot(member.status),
+        name_markup
+    ));
+    name.set_xalign(0.0);
+    name.set_ellipsize(pango::EllipsizeMode::End);
+
+    let text = gtk::Box::new(Orientation::Vertical, 0);
+    text.set_valign(gtk::Align::Center);
+    text.pack_start(&name, false, false, 0);
+    if let Some(activity) = &member.activity {
+        let activity = gtk::Label::new(Some(activity.as_str()));
+        activity.set_xalign(0.0);
+        activity.set_ellipsize(pango::EllipsizeMode::End);
+        activity.get_style_context().add_class("dim-label");
+        text.pack_start(&activity, false, false, 0);
+    }
+
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    row.pack_start(avatar.widget(), false, false, 0);
+    row.pack_start(&text, true, true, 0);
+    if !presence::is_online(member.status) {
+        row.set_opacity(0.5);
+    }
+
+    row
+}
@@ -0,0 +1,2075 @@
+mod attachment;
+mod channel_list;
+mod chat;
+mod code_block;
+mod compose;
+mod dm_list;
+mod embed;
+mod emoji_completion;
+mod emoji_data;
+mod emoji_picker;
+pub mod images;
+pub mod login;
+mod markdown;
+mod member_list;
+mod notifications;
+mod presence;
+mod typing;
+mod unread;
+mod user_panel;
+mod user_picker;
+mod viewer;
+
+use crate::backend::{
+    self, Account, BackendMsg, Cache, ChannelKind, GuildData, NotifyLevel, OwnPresence, UploadFile,
+};
+use channel_list::ChannelList;
+use chat::ChatView;
+use compose::ComposeBox;
+use dm_list::DmList;
+use emoji_picker::EmojiPicker;
+use futures::{channel::mpsc::Receiver, stream::StreamExt};
+use gtk::{
+    AdjustmentExt, BoxExt, ButtonExt, Cast, ContainerExt, DialogExt, FileChooserExt,
+    GtkMenuExtManual, GtkMenuItemExt, GtkWindowExt, Inhibit, ListBoxExt, ListBoxRowExt,
+    MenuShellExt, Orientation, ScrolledWindowExt, SpinnerExt, StackExt, TextBufferExt, TextViewExt,
+    WidgetExt, Window, WindowPosition, WindowType,
+};
+use images::{Avatar, DecodedImage, ImageLoader};
+use member_list::MemberList;
+use notifications::Notifier;
+use relm::{connect, connect_stream, Relm, Update, Widget};
+use relm_derive::Msg;
+use serde::{Deserialize, Serialize};
+use serenity::model::{
+    channel::{Attachment, Channel, Group, GuildChannel, Message, PrivateChannel, ReactionType},
+    guild::{Emoji, Guild, Member, PartialGuild, Role},
+    id::{ChannelId, EmojiId, GuildId, MessageId, RoleId, UserId},
+    user::CurrentUser,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+use typing::TypingIndicator;
+use unread::{Unread, UnreadLabel};
+use user_panel::{OtherAccount, UserPanel};
+
+const GUILD_ICON_RADIUS: f64 = 25.0;
+/// How much of a message a notification shows
+const NOTIFICATION_PREVIEW: usize = 200;
+/// Our own typing is broadcast at most this often, Discord shows it for a while longer
+const TYPING_INTERVAL: Duration = Duration::from_secs(8);
+/// Presence changes are gathered this long before the member list shows them, large
+/// guilds send them all the time
+const PRESENCE_BATCH: Duration = Duration::from_secs(1);
+
+#[derive(Msg)]
+pub enum Msg {
+    /// Asks for someone to add to a group DM
+    AddGroupMember(ChannelId),
+    AddReaction(ChannelId, MessageId, ReactionType),
+    /// Asks for the token of another account to log in to
+    AddAccount,
+    AttachFiles,
+    /// An event of the account with the id
+    Backend(u64, BackendMsg),
+    CancelEdit,
+    /// A channel's row was right-clicked
+    ChannelMenu(usize),
+    ChannelSelected(usize),
+    /// The compose box's text changed
+    Composing,
+    /// A DM's row was right-clicked
+    DmMenu(usize),
+    DmSelected(usize),
+    DownloadAttachment(Attachment),
+    EditLast,
+    FilesDropped(Vec<PathBuf>),
+    /// A guild's row was right-clicked
+    GuildMenu(usize),
+    GuildSelected(usize),
+    /// Lists the DMs in place of a guild's channels
+    HomeSelected,
+    ImageLoaded(u64, Option<DecodedImage>),
+    /// Whether the shown account stays connected after switching to another one
+    KeepConnected(bool),
+    LeaveGroup(ChannelId),
+    LoadReactionUsers(ChannelId, MessageId, ReactionType),
+    MarkChannelRead(ChannelId),
+    MarkGuildRead(u64),
+    /// The member list was scrolled or resized
+    MembersScrolled,
+    /// Shows the presence changes gathered since the member list was last refreshed
+    PresencesChanged,
+    /// Asks for someone to start a DM with
+    NewDm,
+    /// Asks for a new activity to show others
+    EditActivity,
+    LogOut,
+    /// A notification was clicked, it may be for another account than the shown one
+    OpenMessage(u64, ChannelId, MessageId),
+    PasteImage,
+    /// Pops up the menu to set our presence from
+    PresenceMenu,
+    EmojiPicked(EmojiTarget, ReactionType),
+    /// Opens the emoji picker next to the widget
+    PickEmoji(EmojiTarget, gtk::Widget),
+    Quit,
+    RemoveGroupMember(ChannelId, u64),
+    RemoveReaction(ChannelId, MessageId, ReactionType),
+    Send,
+    /// Sets the notification level of a guild or channel by its id
+    SetNotifyLevel(u64, Option<NotifyLevel>),
+    SetPresence(OwnPresence),
+    /// Shows another account we're logged in to by its id
+    SwitchAccount(u64),
+    ViewImage(Attachment),
+}
+
+/// Where a picked emoji goes
+#[derive(Clone, Copy)]
+pub enum EmojiTarget {
+    Compose,
+    Reaction(ChannelId, MessageId),
+}
+
+/// An account's connection along with what's cached and unread for it, kept for the
+/// accounts which stay connected while another one is shown
+struct Session {
+    account_id: u64,
+    discord: backend::Discord,
+    backend_channel: relm::Channel<BackendMsg>,
+    cache: Cache,
+    guilds: Vec<u64>,
+    unread: HashMap<ChannelId, Unread>,
+    requested_members: HashSet<u64>,
+    user_id: UserId,
+}
+
+impl Session {
+    /// Finds the channels with messages newer than the last one read, in guilds and DMs
+    fn load_unread(&mut self) {
+        let mut channels = dms_newest(&self.cache);
+        for guild_id in self.guilds.iter() {
+            channels.extend(guild_newest(&self.cache, *guild_id).into_iter().flatten());
+        }
+
+        for channel_id in unread_channels(&self.discord, channels) {
+            self.unread.entry(channel_id).or_default().unread = true;
+        }
+    }
+
+    fn mentions(&self) -> u32 {
+        self.unread.values().map(|unread| unread.mentions).sum()
+    }
+}
+
+pub struct Win {
+    relm: Relm<Win>,
+    window: Window,
+    /// The id of the shown account, the fields down to `user_id` are its state
+    account_id: u64,
+    discord: backend::Discord,
+    backend_channel: relm::Channel<BackendMsg>,
+    _image_channel: relm::Channel<(u64, Option<DecodedImage>)>,
+    images: ImageLoader,
+    cache: Cache,
+    channel_list: ChannelList,
+    dm_list: DmList,
+    /// Shows either the channel list or the DM list
+    channel_stack: gtk::Stack,
+    member_list: MemberList,
+    user_panel: UserPanel,
+    chat: ChatView,
+    compose: ComposeBox,
+    typing: TypingIndicator,
+    notifier: Notifier,
+    /// The channel our typing was last broadcast in and when
+    last_typing: Option<(ChannelId, Instant)>,
+    home_list: gtk::ListBox,
+    home_label: UnreadLabel,
+    /// Whether Home is selected rather than a guild
+    home: bool,
+    /// Spins while the shown account waits for its first state from the gateway
+    connecting: gtk::Spinner,
+    guild_list: gtk::ListBox,
+    /// The ids of the listed guilds, in the order of their rows
+    guilds: Vec<u64>,
+    guild_labels: HashMap<u64, UnreadLabel>,
+    selected_guild: Option<usize>,
+    /// What's unread in each channel, channels without anything unread are left out
+    unread: HashMap<ChannelId, Unread>,
+    /// The guilds all members were asked for since connecting
+    requested_members: HashSet<u64>,
+    user_id: UserId,
+    /// Whether a refresh of the member list is scheduled for changed presences
+    presences_changed: bool,
+    /// The accounts we're logged in to, the shown one first
+    accounts: Vec<Account>,
+    /// The state of the accounts kept connected in the background, by their ids
+    background: HashMap<u64, Session>,
+}
+
+impl Win {
+    fn handle_backend(&mut self, msg: BackendMsg) {
+        self.cache.update(&msg);
+
+        match msg {
+            BackendMsg::MessageHistory(channel_id, messages) => {
+                if self.chat.channel() == Some(channel_id) {
+                    self.mark_read(channel_id);
+                }
+                self.chat
+                    .set_history(channel_id, messages, &self.cache, &mut self.images)
+            }
+            BackendMsg::MessageAdd(message) => {
+                self.typing.stop(message.channel_id, message.author.id);
+                self.message_unread(&message);
+                self.notify(&message);
+                self.chat.push(&message, &self.cache, &mut self.images);
+                // The DM moves to the top
+                if message.guild_id.is_none() {
+                    self.refresh_dms();
+                }
+            }
+            BackendMsg::MessageUpdate(_, _, event) => {
+                self.chat.update(&event, &self.cache, &mut self.images)
+            }
+            BackendMsg::MessageRm(channel_id, message_id) => {
+                self.chat.remove(channel_id, message_id)
+            }
+            BackendMsg::MessageRmBulk(channel_id, message_ids) => {
+                self.chat.remove_bulk(channel_id, &message_ids)
+            }
+            BackendMsg::Ready(_, state) => self.set_guilds(&state),
+            BackendMsg::GuildCreate(guild, _) => {
+                self.add_guild(guild.id.0);
+                self.load_unread(guild.id.0);
+                self.refresh_custom_emoji();
+            }
+            BackendMsg::GuildUpdate(_, guild) => {
+                self.add_guild(guild.id.0);
+                self.refresh_custom_emoji();
+            }
+            BackendMsg::GuildEmojiUpdate(..) => self.refresh_custom_emoji(),
+            BackendMsg::GuildDel(guild, _) => self.remove_guild(guild.id.0),
+            BackendMsg::ChannelCreate(channel) | BackendMsg::ChannelDelete(channel) => {
+                let guild_id = channel.read().guild_id.0;
+                self.refresh_channels(guild_id);
+            }
+            BackendMsg::ChannelUpdate(_, Channel::Guild(channel)) => {
+                let guild_id = channel.read().guild_id.0;
+                self.refresh_channels(guild_id);
+            }
+            BackendMsg::ChannelUpdate(_, Channel::Category(_)) | BackendMsg::CategoryDelete(_) => {
+                if let Some(guild_id) = self.selected_guild_id() {
+                    self.refresh_channels(guild_id);
+                }
+            }
+            BackendMsg::PrivateChannels(_) => {
+                self.load_dm_unread();
+                self.refresh_dms();
+            }
+            BackendMsg::PrivateChannelCreate(_)
+            | BackendMsg::ChannelUpdate(_, Channel::Private(_))
+            | BackendMsg::ChannelUpdate(_, Channel::Group(_))
+            | BackendMsg::ChannelRecipientAdd(..)
+            | BackendMsg::ChannelRecipientRm(..) => self.refresh_dms(),
+            BackendMsg::PresenceReplace(_) => {
+                self.refresh_dms();
+                if let Some(guild_id) = self.selected_guild_id() {
+                    self.refresh_members(guild_id);
+                }
+            }
+            BackendMsg::PresenceUpdate(event) => {
+                let user_id = event.presence.user_id.0;
+                if self.has_dm_with(user_id) {
+                    self.refresh_dms();
+                }
+
+                let guild_id = self.selected_guild_id().filter(|guild_id| {
+                    self.cache
+                        .guild(*guild_id)
+                        .map_or(false, |guild| guild.members.contains_key(&user_id))
+                });
+                if guild_id.is_some() {
+                    self.presence_changed();
+                }
+            }
+            BackendMsg::GuildMemberAdd(guild_id, _)
+            | BackendMsg::GuildMemberRm(guild_id, ..)
+            | BackendMsg::GuildMembersOffline(guild_id, _)
+            | BackendMsg::GuildRoleAdd(guild_id, _)
+            | BackendMsg::GuildRoleRm(guild_id, ..)
+            | BackendMsg::GuildRoleUpdate(guild_id, ..) => self.refresh_members(guild_id.0),
+            BackendMsg::GuildMemberUpdate(_, member) => self.refresh_members(member.guild_id.0),
+            BackendMsg::DownloadFinished(path, Ok(())) => {
+                self.compose.show_info(&format!("Saved {}", path.display()))
+            }
+            BackendMsg::DownloadFinished(path, Err(err)) => {
+                self.compose
+                    .show_error(&format!("Failed to save {}: {}", path.display(), err))
+            }
+            BackendMsg::ReactionAdd(reaction) => {
+                self.chat
+                    .add_reaction(&reaction, self.user_id, &mut self.images)
+            }
+            BackendMsg::ReactionRm(reaction) => {
+                self.chat
+                    .remove_reaction(&reaction, self.user_id, &mut self.images)
+            }
+            BackendMsg::ReactionRmAll(channel_id, message_id) => {
+                self.chat
+                    .remove_all_reactions(channel_id, message_id, &mut self.images)
+            }
+            BackendMsg::ReactionUsers(channel_id, message_id, emoji, users) => self
+                .chat
+                .set_reaction_users(channel_id, message_id, &emoji, &users),
+            BackendMsg::MessageSent(_, editing, content, Err(err)) => {
+                self.compose.restore(editing, &content);
+                self.compose
+                    .show_error(&format!("Failed to send message: {}", err));
+            }
+            BackendMsg::RequestFailed(err) => self.compose.show_error(&err),
+            BackendMsg::DmOpened(channel) => {
+                let channel_id = channel.read().id;
+                self.handle_backend(BackendMsg::PrivateChannelCreate(channel));
+                self.open_channel(channel_id, None);
+                self.refresh_dms();
+            }
+            BackendMsg::GroupLeft(channel_id) => self.group_left(channel_id),
+            BackendMsg::UploadProgress(upload_id, index, state) => {
+                self.compose.upload_progress(upload_id, index, &state)
+            }
+            BackendMsg::TypingStart(event) if event.user_id != self.user_id => {
+                let name = self
+                    .cache
+                    .display_name(self.selected_guild_id(), event.user_id.0)
+                    .unwrap_or_else(|| "Someone".to_string());
+                self.typing.start(event.channel_id, event.user_id, name);
+            }
+            _ => {}
+        }
+    }
+
+    fn selected_guild_id(&self) -> Option<u64> {
+        self.selected_guild
+            .and_then(|index| self.guilds.get(index))
+            .cloned()
+    }
+
+    /// Replaces the listed guilds with the ones of a fresh initial state
+    fn set_guilds(&mut self, state: &InitializationState) {
+        set_connecting(&self.connecting, false);
+        let stale = self
+            .guilds
+            .iter()
+            .filter(|listed| !state.guilds.iter().any(|guild| guild.id.0 == **listed))
+            .cloned()
+            .collect::<Vec<_>>();
+        for guild_id in stale {
+            self.remove_guild(guild_id);
+        }
+
+        self.user_panel
+            .set_user(&state.user.name, Some(state.user.face()), &mut self.images);
+
+        // A new session knows only about the members the gateway sent it
+        self.requested_members.clear();
+        for guild in state.guilds.iter() {
+            self.add_guild(guild.id.0);
+            self.load_unread(guild.id.0);
+        }
+        self.load_dm_unread();
+        self.refresh_dms();
+        // The gateway leaves out DMs which haven't been active in a while
+        self.discord.load_dm_channels();
+
+        if let Some(channel_id) = self.chat.channel() {
+            self.discord.load_messages(channel_id);
+        }
+    }
+
+    /// Lists a guild which became available or changed after startup, rebuilding its row
+    /// if it's already listed
+    fn add_guild(&mut self, guild_id: u64) {
+        let (row, label) = match self.cache.guild(guild_id) {
+            Some(guild) => guild_row(&guild, &mut self.images),
+            None => return,
+        };
+        label.set(self.guild_unread(guild_id));
+        self.guild_labels.insert(guild_id, label);
+        row.show_all();
+
+        match self.guilds.iter().position(|listed| *listed == guild_id) {
+            Some(index) => {
+                if let Some(list_row) = self.guild_list.get_row_at_index(index as i32) {
+                    for child in list_row.get_children() {
+                        list_row.remove(&child);
+                    }
+                    list_row.add(&row);
+                }
+
+                self.refresh_channels(guild_id);
+                self.refresh_members(guild_id);
+            }
+            None => {
+                self.guild_list.add(&row);
+                self.guilds.push(guild_id);
+            }
+        }
+    }
+
+    fn remove_guild(&mut self, guild_id: u64) {
+        let index = match self.guilds.iter().position(|listed| *listed == guild_id) {
+            Some(index) => index,
+            None => return,
+        };
+
+        if let Some(row) = self.guild_list.get_row_at_index(index as i32) {
+            self.guild_list.remove(&row);
+        }
+        self.guilds.remove(index);
+        self.guild_labels.remove(&guild_id);
+
+        match self.selected_guild {
+            Some(selected) if selected == index => self.selected_guild = None,
+            Some(selected) if selected > index => self.selected_guild = Some(selected - 1),
+            _ => {}
+        }
+    }
+
+    fn refresh_channels(&mut self, guild_id: u64) {
+        if self.selected_guild_id() != Some(guild_id) {
+            return;
+        }
+
+        if let Some(guild) = self.cache.guild(guild_id) {
+            self.channel_list
+                .set_channels(&guild.channels, self.chat.channel(), &self.unread);
+        }
+    }
+
+    fn refresh_members(&mut self, guild_id: u64) {
+        if self.selected_guild_id() != Some(guild_id) {
+            return;
+        }
+
+        if let Some(guild) = self.cache.guild(guild_id) {
+            self.member_list
+                .set_members(guild_id, &guild, &self.cache, &mut self.images);
+        }
+    }
+
+    /// Refreshes the member list a while after a presence changed, along with any
+    /// others that change in the meantime
+    fn presence_changed(&mut self) {
+        if self.presences_changed {
+            return;
+        }
+        self.presences_changed = true;
+
+        let stream = self.relm.stream().clone();
+        gtk::timeout_add(PRESENCE_BATCH.as_millis() as u32, move || {
+            stream.emit(Msg::PresencesChanged);
+            glib::Continue(false)
+        });
+    }
+
+    /// Builds the members which came into view, asking for the rest of the guild's
+    /// members once the last of those known are
+    fn members_scrolled(&mut self) {
+        self.member_list.scrolled(&mut self.images);
+
+        if let Some(guild_id) = self.selected_guild_id() {
+            if self.member_list.at_end() && self.requested_members.insert(guild_id) {
+                self.discord.request_members(guild_id);
+            }
+        }
+    }
+
+    /// Relists the DMs if Home is selected
+    fn refresh_dms(&mut self) {
+        if !self.home {
+            return;
+        }
+
+        self.dm_list.set_channels(
+            &self.cache,
+            self.chat.channel(),
+            &self.unread,
+            &mut self.images,
+        );
+    }
+
+    /// Whether we have a DM or group DM with the user
+    fn has_dm_with(&self, user_id: u64) -> bool {
+        self.cache.dm_ids().into_iter().any(|channel_id| {
+            self.cache
+                .dm(channel_id)
+                .map_or(false, |dm| dm.recipients.contains(&user_id))
+        })
+    }
+
+    /// Finds the guild's channels with messages newer than the last one read
+    fn load_unread(&mut self, guild_id: u64) {
+        let channels = match guild_newest(&self.cache, guild_id) {
+            Some(channels) => channels,
+            None => return,
+        };
+
+        self.load_channels_unread(channels);
+        self.show_guild_unread(guild_id);
+    }
+
+    /// Finds the DMs with messages newer than the last one read
+    fn load_dm_unread(&mut self) {
+        let channels = dms_newest(&self.cache);
+        self.load_channels_unread(channels);
+        self.show_home_unread();
+    }
+
+    /// Marks the channels with a newer message than the last one read as unread
+    fn load_channels_unread(&mut self, channels: Vec<(ChannelId, MessageId)>) {
+        for channel_id in unread_channels(&self.discord, channels) {
+            self.unread.entry(channel_id).or_default().unread = true;
+            self.channel_list
+                .set_unread(channel_id, self.unread[&channel_id]);
+            self.dm_list
+                .set_unread(channel_id, self.unread[&channel_id]);
+        }
+    }
+
+    /// Counts a new message as unread, unless it's our own or its channel is being read
+    fn message_unread(&mut self, message: &Message) {
+        if message.author.id == self.user_id || self.chat.channel() == Some(message.channel_id) {
+            return self.mark_read(message.channel_id);
+        }
+
+        count_unread(&mut self.unread, message, &self.cache, self.user_id);
+        self.show_unread(message.channel_id);
+    }
+
+    /// Shows a desktop notification for a message which isn't in plain sight, if its
+    /// channel's notification level asks for it
+    fn notify(&mut self, message: &Message) {
+        let in_sight = self.window.is_active() && self.chat.channel() == Some(message.channel_id);
+        if in_sight || !notifies(message, &self.discord, &self.cache, self.user_id) {
+            return;
+        }
+
+        let (title, body) = notification(message, &self.cache);
+        let icon = self.images.cached(&message.author.face());
+        self.notifier.notify(
+            self.account_id,
+            message.channel_id,
+            message.id,
+            &title,
+            &body,
+            icon,
+        );
+    }
+
+    fn mark_read(&mut self, channel_id: ChannelId) {
+        if let Some(newest) = self
+            .cache
+            .channel(channel_id.0)
+            .and_then(|channel| channel.last_message_id)
+        {
+            self.discord.mark_read(channel_id, MessageId(newest));
+        }
+        self.notifier.withdraw(channel_id);
+
+        if self.unread.remove(&channel_id).is_some() {
+            self.show_unread(channel_id);
+        }
+    }
+
+    fn open_channel(&mut self, channel_id: ChannelId, guild_id: Option<u64>) {
+        self.chat.set_channel(channel_id, guild_id);
+        self.typing.set_channel(channel_id);
+        self.mark_read(channel_id);
+        self.compose.clear();
+        self.discord.load_messages(channel_id);
+    }
+
+    /// Brings the window up with the message's channel open and scrolled to it, showing
+    /// the message's account first if it's another one
+    fn open_message(&mut self, account_id: u64, channel_id: ChannelId, message_id: MessageId) {
+        self.window.present();
+        if account_id != self.account_id {
+            self.switch_account(account_id);
+        }
+
+        let guild_id = self.cache.guild_with_channel(channel_id.0);
+        if self.chat.channel() != Some(channel_id) {
+            self.open_channel(channel_id, guild_id);
+        }
+        self.chat.jump_to(message_id);
+
+        let index = guild_id.and_then(|guild_id| self.guilds.iter().position(|id| *id == guild_id));
+        match (index, guild_id) {
+            (Some(index), Some(guild_id)) if self.selected_guild == Some(index) => {
+                self.refresh_channels(guild_id)
+            }
+            (Some(index), _) => {
+                if let Some(row) = self.guild_list.get_row_at_index(index as i32) {
+                    self.guild_list.select_row(Some(&row));
+                }
+            }
+            (None, None) if self.home => self.refresh_dms(),
+            (None, None) => {
+                if let Some(row) = self.home_list.get_row_at_index(0) {
+                    self.home_list.select_row(Some(&row));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Pops up the context menu of a guild's, channel's or DM's row, followed by any extra
+    /// items
+    fn row_menu(
+        &self,
+        id: u64,
+        mark_read: fn(u64) -> Msg,
+        default_level: NotifyLevel,
+        extra: &[gtk::MenuItem],
+    ) {
+        let (read_stream, level_stream) = (self.relm.stream().clone(), self.relm.stream().clone());
+        let items = [
+            unread::mark_read_item(move || read_stream.emit(mark_read(id))),
+            notifications::level_item(self.discord.notify_level(id), default_level, move |level| {
+                level_stream.emit(Msg::SetNotifyLevel(id, level))
+            }),
+        ];
+
+        let menu = gtk::Menu::new();
+        for item in items.iter().chain(extra) {
+            menu.append(item);
+        }
+        menu.show_all();
+        menu.popup_easy(3, gtk::get_current_event_time());
+    }
+
+    /// A DM's context menu, which groups also manage their members from
+    fn dm_menu(&self, channel_id: ChannelId) {
+        let dm = match self.cache.dm(channel_id.0) {
+            Some(dm) => (*dm).clone(),
+            None => return,
+        };
+
+        let mut extra = Vec::new();
+        if dm.kind == ChannelKind::Group {
+            let stream = self.relm.stream().clone();
+            let add = gtk::MenuItem::new_with_label("Add Member…");
+            add.connect_activate(move |_| stream.emit(Msg::AddGroupMember(channel_id)));
+            extra.push(add);
+
+            let members = gtk::Menu::new();
+            for user_id in dm.recipients.iter().cloned() {
+                let name = self
+                    .cache
+                    .user(user_id)
+                    .map_or_else(|| user_id.to_string(), |user| user.name.clone());
+                let stream = self.relm.stream().clone();
+                let member = gtk::MenuItem::new_with_label(&name);
+                member.connect_activate(move |_| {
+                    stream.emit(Msg::RemoveGroupMember(channel_id, user_id))
+                });
+                members.append(&member);
+            }
+            let remove = gtk::MenuItem::new_with_label("Remove Member");
+            remove.set_submenu(Some(&members));
+            extra.push(remove);
+
+            let stream = self.relm.stream().clone();
+            let leave = gtk::MenuItem::new_with_label("Leave Group");
+            leave.connect_activate(move |_| stream.emit(Msg::LeaveGroup(channel_id)));
+            extra.push(leave);
+        }
+
+        self.row_menu(
+            channel_id.0,
+            |channel_id| Msg::MarkChannelRead(ChannelId(channel_id)),
+            NotifyLevel::All,
+            &extra,
+        );
+    }
+
+    /// Asks to pick one of the users we know of, other than ourselves and those excluded
+    fn pick_user(&self, title: &str, excluded: &[u64]) -> Option<u64> {
+        let users = self
+            .cache
+            .user_ids()
+            .into_iter()
+            .filter(|user_id| *user_id != self.user_id.0 && !excluded.contains(user_id))
+            .filter_map(|user_id| Some((user_id, self.cache.user(user_id)?.name.clone())))
+            .collect();
+
+        user_picker::pick_user(&self.window, title, users)
+    }
+
+    /// Opens the DM with a picked user, starting one if there isn't one yet
+    fn new_dm(&self) {
+        let user_id = match self.pick_user("New Message", &[]) {
+            Some(user_id) => user_id,
+            None => return,
+        };
+
+        self.discord.open_dm(user_id);
+    }
+
+    fn add_group_member(&self, channel_id: ChannelId) {
+        let members = self
+            .cache
+            .dm(channel_id.0)
+            .map(|dm| dm.recipients.clone())
+            .unwrap_or_default();
+        let user_id = match self.pick_user("Add Member", &members) {
+            Some(user_id) => user_id,
+            None => return,
+        };
+
+        self.discord.add_group_member(channel_id.0, user_id);
+    }
+
+    /// Forgets a group DM we left
+    fn group_left(&mut self, channel_id: ChannelId) {
+        self.cache.remove_dm(channel_id.0);
+        self.unread.remove(&channel_id);
+        self.notifier.withdraw(channel_id);
+        self.show_home_unread();
+        self.refresh_dms();
+    }
+
+    fn mark_guild_read(&mut self, guild_id: u64) {
+        let channels = self
+            .cache
+            .guild(guild_id)
+            .map(|guild| guild.channels.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        for channel_id in channels {
+            self.mark_read(ChannelId(channel_id));
+        }
+    }
+
+    /// Updates a channel's row and its guild's row, or Home's for DMs, to what's unread in
+    /// the channel
+    fn show_unread(&self, channel_id: ChannelId) {
+        let unread = self.unread.get(&channel_id).cloned().unwrap_or_default();
+        self.channel_list.set_unread(channel_id, unread);
+        self.dm_list.set_unread(channel_id, unread);
+
+        match self.cache.guild_with_channel(channel_id.0) {
+            Some(guild_id) => self.show_guild_unread(guild_id),
+            None => self.show_home_unread(),
+        }
+    }
+
+    /// Shows what's unread in all DMs on Home's row
+    fn show_home_unread(&self) {
+        self.home_label.set(Unread::sum(
+            self.cache
+                .dm_ids()
+                .into_iter()
+                .filter_map(|channel_id| self.unread.get(&ChannelId(channel_id)).cloned()),
+        ));
+    }
+
+    fn show_guild_unread(&self, guild_id: u64) {
+        if let Some(label) = self.guild_labels.get(&guild_id) {
+            label.set(self.guild_unread(guild_id));
+        }
+    }
+
+    /// What's unread in all channels of a guild
+    fn guild_unread(&self, guild_id: u64) -> Unread {
+        let channels = self
+            .cache
+            .guild(guild_id)
+            .map(|guild| guild.channels.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        Unread::sum(
+            channels
+                .into_iter()
+                .filter_map(|channel_id| self.unread.get(&ChannelId(channel_id)).cloned()),
+        )
+    }
+
+    fn send(&mut self) {
+        let channel_id = match self.chat.channel() {
+            Some(channel_id) => channel_id,
+            None => {
+                return self
+                    .compose
+                    .show_error("Select a channel to send messages to")
+            }
+        };
+
+        let content = match self.compose.content() {
+            Ok(content) => content,
+            Err(err) => return self.compose.show_error(&err),
+        };
+
+        // Attachments don't need any text to go along with them
+        if self.compose.editing().is_none() && self.compose.has_attachments() {
+            let (upload_id, files) = self.compose.start_upload(content.clone());
+            self.discord.upload(upload_id, channel_id, content, files);
+            self.last_typing = None;
+            return self.compose.clear();
+        }
+
+        let content = match content {
+            Some(content) => content,
+            None => return,
+        };
+
+        self.discord
+            .send_message(channel_id, self.compose.editing(), content);
+        // Discord stops showing us as typing once the message arrives
+        self.last_typing = None;
+        self.compose.clear();
+    }
+
+    /// Lets the others in the channel know we're typing, unless it's only an edit
+    fn composing(&mut self) {
+        let channel_id = match self.chat.channel() {
+            Some(channel_id) => channel_id,
+            None => return,
+        };
+        if self.compose.is_empty() || self.compose.editing().is_some() {
+            return;
+        }
+
+        let recently_sent = match self.last_typing {
+            Some((last_channel, sent)) => {
+                last_channel == channel_id && sent.elapsed() < TYPING_INTERVAL
+            }
+            None => false,
+        };
+        if !recently_sent {
+            self.last_typing = Some((channel_id, Instant::now()));
+            self.discord.broadcast_typing(channel_id);
+        }
+    }
+
+    fn attach(&self, file: UploadFile) {
+        if let Err(err) = self.compose.add_attachment(file) {
+            self.compose.show_error(&err);
+        }
+    }
+
+    fn choose_files(&self) {
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Attach Files"),
+            Some(&self.window),
+            gtk::FileChooserAction::Open,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Attach", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_select_multiple(true);
+
+        let response = dialog.run();
+        let files = dialog.get_filenames();
+        dialog.destroy();
+
+        if response == gtk::ResponseType::Accept.into() {
+            for path in files {
+                self.attach(UploadFile::Path(path));
+            }
+        }
+    }
+
+    /// Attaches the image on the clipboard as a PNG
+    fn paste_image(&self) {
+        let image = match gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).wait_for_image() {
+            Some(image) => image,
+            None => return,
+        };
+
+        match image.save_to_bufferv("png", &[]) {
+            Ok(bytes) => self.attach(UploadFile::Bytes("image.png".to_string(), bytes)),
+            Err(err) => self
+                .compose
+                .show_error(&format!("Failed to paste image: {}", err)),
+        }
+    }
+
+    /// Asks where to save the attachment, confirming before an existing file is replaced
+    fn download(&self, attachment: &Attachment) {
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Save Attachment"),
+            Some(&self.window),
+            gtk::FileChooserAction::Save,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Save", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        // Only the file name is suggested, the attachment's may contain a path
+        if let Some(name) = Path::new(&attachment.filename).file_name() {
+            dialog.set_current_name(name);
+        }
+
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.destroy();
+
+        if response != gtk::ResponseType::Accept.into() {
+            return;
+        }
+
+        if let Some(path) = path {
+            self.discord.download(attachment.url.clone(), path);
+        }
+    }
+
+    fn pick_emoji(&mut self, target: EmojiTarget, widget: &gtk::Widget) {
+        let stream = self.relm.stream().clone();
+        let picker = EmojiPicker::new(
+            widget,
+            &self.discord.frequent_emoji(),
+            &self.cache,
+            self.selected_guild_id(),
+            &mut self.images,
+            move |emoji| stream.emit(Msg::EmojiPicked(target, emoji)),
+        );
+        picker.show();
+    }
+
+    fn emoji_picked(&self, target: EmojiTarget, emoji: &ReactionType) {
+        self.discord.count_emoji(emoji);
+
+        match target {
+            EmojiTarget::Compose => self.compose.insert_emoji(emoji),
+            EmojiTarget::Reaction(channel_id, message_id) => {
+                self.discord
+                    .react(channel_id, message_id, emoji.clone(), true)
+            }
+        }
+    }
+
+    /// Offers the custom emoji of every guild for completion, the selected guild's first
+    fn refresh_custom_emoji(&self) {
+        let emoji = emoji_picker::custom_emoji(&self.cache, self.selected_guild_id())
+            .into_iter()
+            .flat_map(|(_, emoji)| emoji)
+            .collect();
+        self.compose.set_custom_emoji(emoji);
+    }
+
+    /// Pops up the menu to set our presence from, followed by the items to manage our
+    /// accounts
+    fn presence_menu(&self) {
+        let (select_stream, edit_stream) = (self.relm.stream().clone(), self.relm.stream().clone());
+        let menu = user_panel::presence_menu(
+            &self.discord.presence(),
+            move |presence| select_stream.emit(Msg::SetPresence(presence)),
+            move || edit_stream.emit(Msg::EditActivity),
+        );
+
+        let others = self
+            .accounts
+            .iter()
+            .skip(1)
+            .map(|account| OtherAccount {
+                id: account.id,
+                name: account_name(account),
+                mentions: self
+                    .background
+                    .get(&account.id)
+                    .map_or(0, Session::mentions),
+            })
+            .collect::<Vec<_>>();
+        let keep_connected = self
+            .accounts
+            .first()
+            .map_or(false, |account| account.keep_connected);
+        let (switch_stream, add_stream, keep_stream, log_out_stream) = (
+            self.relm.stream().clone(),
+            self.relm.stream().clone(),
+            self.relm.stream().clone(),
+            self.relm.stream().clone(),
+        );
+        user_panel::account_items(
+            &menu,
+            &others,
+            keep_connected,
+            move |account_id| switch_stream.emit(Msg::SwitchAccount(account_id)),
+            move || add_stream.emit(Msg::AddAccount),
+            move |keep| keep_stream.emit(Msg::KeepConnected(keep)),
+            move || log_out_stream.emit(Msg::LogOut),
+        );
+        menu.show_all();
+        menu.popup_easy(1, gtk::get_current_event_time());
+    }
+
+    fn set_presence(&self, presence: &OwnPresence) {
+        self.discord.set_presence(presence);
+        self.user_panel.set_presence(presence);
+    }
+
+    fn edit_activity(&self) {
+        let current = self.discord.presence();
+        let activity =
+            user_panel::ask_activity(&self.window, current.activity.as_ref().map(String::as_str));
+
+        if let Some(activity) = activity {
+            self.set_presence(&OwnPresence {
+                activity: Some(activity).filter(|activity| !activity.is_empty()),
+                ..current
+            });
+        }
+    }
+
+    /// Logs out of the shown account, forgetting its token and everything stored for it,
+    /// and shows the next one. Asks for an account to log in to if it was the last one,
+    /// quitting if none is given
+    fn log_out(&mut self) {
+        self.discord.log_out();
+        self.accounts.remove(0);
+        self.save_accounts();
+
+        let next = self.accounts.first().map(|account| account.id);
+        if let Some(account_id) = next {
+            // The account isn't listed anymore, so it's disconnected rather than kept
+            self.switch_account(account_id);
+            return self.select_home();
+        }
+
+        let account = match login::ask_account(Some(&self.window), &[], None) {
+            Some(account) => account,
+            None => return gtk::main_quit(),
+        };
+        // Not switched to, as the account may get the id of the one logged out of
+        let mut session = self.connect(&account);
+        self.swap_session(&mut session);
+        session.discord.shut_down();
+
+        self.accounts.push(account);
+        self.save_accounts();
+        self.show_session();
+        self.select_home();
+    }
+
+    /// Asks for another account to log in to and shows it
+    fn add_account(&mut self) {
+        if let Some(account) = login::ask_account(Some(&self.window), &self.accounts, None) {
+            let account_id = account.id;
+            self.accounts.push(account);
+            self.switch_account(account_id);
+            self.select_home();
+        }
+    }
+
+    fn keep_connected(&mut self, keep: bool) {
+        if let Some(account) = self.accounts.first_mut() {
+            account.keep_connected = keep;
+        }
+        self.save_accounts();
+    }
+
+    /// Stores the accounts, all but the one of `DISCORD_TOKEN`
+    fn save_accounts(&self) {
+        let stored = self
+            .accounts
+            .iter()
+            .filter(|account| !account.temporary)
+            .cloned()
+            .collect::<Vec<_>>();
+        if stored.is_empty() {
+            return backend::forget_accounts();
+        }
+
+        if let Err(err) = backend::save_accounts(&stored) {
+            eprintln!("Credentials Error: {}", err);
+        }
+    }
+
+    /// Keeps the name and id of an account's user once its connection is ready, they're
+    /// shown in the account items and tell apart the accounts we're logged in to
+    fn account_ready(&mut self, account_id: u64, user: &CurrentUser) {
+        let account = match self
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == account_id)
+        {
+            Some(account) => account,
+            None => return,
+        };
+        if account.user_id == user.id.0 && account.name == user.name {
+            return;
+        }
+
+        account.user_id = user.id.0;
+        account.name = user.name.clone();
+        self.save_accounts();
+    }
+
+    /// Connects to an account with its own backend, starting out with its stored state
+    /// until the gateway's arrives
+    fn connect(&self, account: &Account) -> Session {
+        let (discord, backend_recv) = self.discord.spawn_another(&account.token, account.id);
+        let backend_channel = backend_channel(&self.relm, account.id, backend_recv);
+
+        let cache = Cache::new();
+        let (guilds, user_id) = match discord.stored_state() {
+            Some(state) => {
+                cache.load(&state);
+                let guilds = state.guilds.iter().map(|guild| guild.id.0).collect();
+                (guilds, state.user.id)
+            }
+            None => (Vec::new(), UserId(account.user_id)),
+        };
+
+        let mut session = Session {
+            account_id: account.id,
+            discord,
+            backend_channel,
+            cache,
+            guilds,
+            unread: HashMap::new(),
+            requested_members: HashSet::new(),
+            user_id,
+        };
+        session.load_unread();
+
+        session
+    }
+
+    /// Trades the shown account's state for another's
+    fn swap_session(&mut self, session: &mut Session) {
+        mem::swap(&mut self.account_id, &mut session.account_id);
+        mem::swap(&mut self.discord, &mut session.discord);
+        mem::swap(&mut self.backend_channel, &mut session.backend_channel);
+        mem::swap(&mut self.cache, &mut session.cache);
+        mem::swap(&mut self.guilds, &mut session.guilds);
+        mem::swap(&mut self.unread, &mut session.unread);
+        mem::swap(&mut self.requested_members, &mut session.requested_members);
+        mem::swap(&mut self.user_id, &mut session.user_id);
+    }
+
+    /// Shows another account we're logged in to, connecting to it unless it's connected
+    /// already. The account shown before stays connected in the background if it's meant
+    /// to, and is disconnected otherwise
+    fn switch_account(&mut self, account_id: u64) {
+        if account_id == self.account_id {
+            return;
+        }
+        let account = match self
+            .accounts
+            .iter()
+            .position(|account| account.id == account_id)
+        {
+            Some(index) => self.accounts.remove(index),
+            None => return,
+        };
+
+        let mut session = match self.background.remove(&account_id) {
+            Some(session) => session,
+            None => self.connect(&account),
+        };
+        self.swap_session(&mut session);
+
+        let keep = self
+            .accounts
+            .iter()
+            .any(|account| account.id == session.account_id && account.keep_connected);
+        if keep {
+            self.background.insert(session.account_id, session);
+        } else {
+            session.discord.shut_down();
+        }
+
+        self.accounts.insert(0, account);
+        self.save_accounts();
+        self.show_session();
+    }
+
+    /// Rebuilds everything shown from the shown account's state, with nothing selected
+    fn show_session(&mut self) {
+        for row in self.guild_list.get_children() {
+            self.guild_list.remove(&row);
+        }
+        self.guild_labels.clear();
+        self.selected_guild = None;
+        for guild_id in mem::replace(&mut self.guilds, Vec::new()) {
+            self.add_guild(guild_id);
+        }
+
+        self.chat.clear();
+        self.compose.clear();
+        self.member_list.clear();
+        self.channel_list
+            .set_channels(&HashMap::new(), None, &self.unread);
+        self.last_typing = None;
+        self.show_home_unread();
+
+        let (name, avatar_url) = match self.cache.user(self.user_id.0) {
+            Some(user) => (user.name.clone(), Some(user.avatar.clone())),
+            None => (account_name(&self.accounts[0]), None),
+        };
+        // Only the gateway tells about the user of an account without a stored state
+        set_connecting(&self.connecting, avatar_url.is_none());
+        self.user_panel
+            .set_user(&name, avatar_url, &mut self.images);
+        self.user_panel.set_presence(&self.discord.presence());
+
+        self.home = false;
+        self.home_list.unselect_all();
+    }
+
+    fn select_home(&self) {
+        if let Some(row) = self.home_list.get_row_at_index(0) {
+            self.home_list.select_row(Some(&row));
+        }
+    }
+
+    /// Keeps a background account's cache and unread channels up to date, and notifies
+    /// about its messages the way the shown account's are
+    fn handle_background(&mut self, account_id: u64, msg: BackendMsg) {
+        let session = match self.background.get_mut(&account_id) {
+            Some(session) => session,
+            None => return,
+        };
+        session.cache.update(&msg);
+
+        match msg {
+            BackendMsg::Ready(_, state) => {
+                session.user_id = state.user.id;
+                session.guilds = state.guilds.iter().map(|guild| guild.id.0).collect();
+                session.requested_members.clear();
+                session.load_unread();
+                session.discord.load_dm_channels();
+            }
+            BackendMsg::GuildCreate(guild, _) => {
+                if !session.guilds.contains(&guild.id.0) {
+                    session.guilds.push(guild.id.0);
+                }
+            }
+            BackendMsg::GuildDel(guild, _) => session.guilds.retain(|listed| *listed != guild.id.0),
+            BackendMsg::GroupLeft(channel_id) => {
+                session.cache.remove_dm(channel_id.0);
+                session.unread.remove(&channel_id);
+            }
+            BackendMsg::MessageAdd(message) => {
+                if message.author.id == session.user_id {
+                    session.discord.mark_read(message.channel_id, message.id);
+                    session.unread.remove(&message.channel_id);
+                    return self.notifier.withdraw(message.channel_id);
+                }
+
+                count_unread(
+                    &mut session.unread,
+                    &message,
+                    &session.cache,
+                    session.user_id,
+                );
+                if !notifies(&message, &session.discord, &session.cache, session.user_id) {
+                    return;
+                }
+
+                let (title, body) = notification(&message, &session.cache);
+                let title = match self
+                    .accounts
+                    .iter()
+                    .find(|account| account.id == account_id)
+                {
+                    Some(account) => format!("{} · {}", title, account_name(account)),
+                    None => title,
+                };
+                let icon = self.images.cached(&message.author.face());
+                self.notifier.notify(
+                    account_id,
+                    message.channel_id,
+                    message.id,
+                    &title,
+                    &body,
+                    icon,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn edit_last(&mut self) {
+        if let Some((message_id, content)) = self.chat.last_message_by(self.user_id) {
+            self.compose.start_edit(message_id, content);
+        }
+    }
+}
+
+impl Update for Win {
+    /// The accounts to log in to, the one to show first
+    type Model = Vec<Account>;
+    type ModelParam = Vec<Account>;
+    type Msg = Msg;
+
+    fn model(_: &Relm<Self>, accounts: Vec<Account>) -> Self::Model {
+        accounts
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Msg::Backend(account_id, msg) => {
+                if let BackendMsg::Ready(_, state) = &msg {
+                    self.account_ready(account_id, &state.user);
+                }
+
+                match msg {
+                    // Downloads are reported wherever they were started from
+                    BackendMsg::DownloadFinished(..) => self.handle_backend(msg),
+                    _ if account_id == self.account_id => self.handle_backend(msg),
+                    _ => self.handle_background(account_id, msg),
+                }
+            }
+            Msg::ImageLoaded(id, image) => self.images.loaded(id, image),
+            Msg::MembersScrolled => self.members_scrolled(),
+            Msg::PresencesChanged => {
+                self.presences_changed = false;
+                if let Some(guild_id) = self.selected_guild_id() {
+                    self.refresh_members(guild_id);
+                }
+            }
+            Msg::GuildSelected(row) => {
+                self.home = false;
+                self.home_list.unselect_all();
+                self.channel_stack.set_visible_child_name("channels");
+                self.member_list.widget().show();
+                let guild_id = self.guilds.get(row).cloned();
+                if let Some((guild_id, guild)) =
+                    guild_id.and_then(|guild_id| Some((guild_id, self.cache.guild(guild_id)?)))
+                {
+                    self.selected_guild = Some(row);
+                    // The open channel stays open if it's in the guild, which it is when
+                    // a notification of the guild was clicked
+                    self.channel_list.set_channels(
+                        &guild.channels,
+                        self.chat.channel(),
+                        &self.unread,
+                    );
+                    self.member_list
+                        .set_members(guild_id, &guild, &self.cache, &mut self.images);
+                }
+                self.refresh_custom_emoji();
+                self.members_scrolled();
+            }
+            Msg::HomeSelected => {
+                self.home = true;
+                self.selected_guild = None;
+                self.guild_list.unselect_all();
+                self.channel_stack.set_visible_child_name("dms");
+                self.member_list.widget().hide();
+                self.refresh_dms();
+                self.refresh_custom_emoji();
+            }
+            Msg::DmSelected(row) => {
+                if let Some(channel_id) = self.dm_list.channel_at(row) {
+                    if self.chat.channel() != Some(channel_id) {
+                        self.open_channel(channel_id, None);
+                    }
+                }
+            }
+            Msg::ChannelSelected(row) => {
+                if let Some(channel_id) = self.channel_list.channel_at(row) {
+                    if self.chat.channel() != Some(channel_id) {
+                        self.open_channel(channel_id, self.selected_guild_id());
+                    }
+                }
+            }
+            Msg::CancelEdit => {
+                if self.compose.editing().is_some() {
+                    self.compose.clear();
+                }
+            }
+            Msg::EditLast => self.edit_last(),
+            Msg::GuildMenu(row) => {
+                if let Some(guild_id) = self.guilds.get(row).cloned() {
+                    self.row_menu(guild_id, Msg::MarkGuildRead, NotifyLevel::Mentions, &[]);
+                }
+            }
+            Msg::ChannelMenu(row) => {
+                if let Some(channel_id) = self.channel_list.channel_at(row) {
+                    let default = self
+                        .selected_guild_id()
+                        .and_then(|guild_id| self.discord.notify_level(guild_id))
+                        .unwrap_or(NotifyLevel::Mentions);
+                    self.row_menu(
+                        channel_id.0,
+                        |channel_id| Msg::MarkChannelRead(ChannelId(channel_id)),
+                        default,
+                        &[],
+                    );
+                }
+            }
+            Msg::DmMenu(row) => {
+                if let Some(channel_id) = self.dm_list.channel_at(row) {
+                    self.dm_menu(channel_id);
+                }
+            }
+            Msg::NewDm => self.new_dm(),
+            Msg::AddGroupMember(channel_id) => self.add_group_member(channel_id),
+            Msg::RemoveGroupMember(channel_id, user_id) => {
+                self.discord.remove_group_member(channel_id.0, user_id)
+            }
+            Msg::LeaveGroup(channel_id) => self.discord.leave_group(channel_id.0),
+            Msg::MarkChannelRead(channel_id) => self.mark_read(channel_id),
+            Msg::MarkGuildRead(guild_id) => self.mark_guild_read(guild_id),
+            Msg::SetNotifyLevel(id, level) => self.discord.set_notify_level(id, level),
+            Msg::PresenceMenu => self.presence_menu(),
+            Msg::SetPresence(presence) => self.set_presence(&presence),
+            Msg::EditActivity => self.edit_activity(),
+            Msg::LogOut => self.log_out(),
+            Msg::AddAccount => self.add_account(),
+            Msg::SwitchAccount(account_id) => {
+                self.switch_account(account_id);
+                self.select_home();
+            }
+            Msg::KeepConnected(keep) => self.keep_connected(keep),
+            Msg::OpenMessage(account_id, channel_id, message_id) => {
+                self.open_message(account_id, channel_id, message_id)
+            }
+            Msg::Composing => self.composing(),
+            Msg::AttachFiles => self.choose_files(),
+            Msg::FilesDropped(paths) => {
+                for path in paths {
+                    self.attach(UploadFile::Path(path));
+                }
+            }
+            Msg::PasteImage => self.paste_image(),
+            Msg::ViewImage(attachment) => viewer::show(&self.window, &attachment, &mut self.images),
+            Msg::DownloadAttachment(attachment) => self.download(&attachment),
+            Msg::AddReaction(channel_id, message_id, emoji) => {
+                self.discord.react(channel_id, message_id, emoji, true)
+            }
+            Msg::RemoveReaction(channel_id, message_id, emoji) => {
+                self.discord.react(channel_id, message_id, emoji, false)
+            }
+            Msg::PickEmoji(target, widget) => self.pick_emoji(target, &widget),
+            Msg::EmojiPicked(target, emoji) => self.emoji_picked(target, &emoji),
+            Msg::LoadReactionUsers(channel_id, message_id, emoji) => self
+                .discord
+                .load_reaction_users(channel_id, message_id, emoji),
+            Msg::Send => self.send(),
+            Msg::Quit => gtk::main_quit(),
+        }
+    }
+}
+
+impl Widget for Win {
+    type Root = Window;
+
+    fn root(&self) -> Self::Root {
+        self.window.clone()
+    }
+
+    fn view(relm: &Relm<Self>, accounts: Self::Model) -> Self {
+        let account_id = accounts[0].id;
+        let (discord, backend_recv, url_sender, file_recv) =
+            backend::main(&accounts[0].token, account_id);
+
+        let mut images = ImageLoader::new(url_sender);
+
+        // Start out with the stored state when there is one, the gateway's state replaces
+        // it once connected. Without one the window shows that it's connecting until then
+        let state = discord.stored_state();
+        let cache = Cache::new();
+        let guilds = match &state {
+            Some(state) => {
+                cache.load(state);
+                state.guilds.iter().map(|guild| guild.id.0).collect()
+            }
+            None => Vec::new(),
+        };
+
+        let backend_channel = backend_channel(relm, account_id, backend_recv);
+        let image_channel = {
+            let stream = relm.stream().clone();
+            let (channel, sender) =
+                relm::Channel::new(move |(id, image)| stream.emit(Msg::ImageLoaded(id, image)));
+            pump("Image Pump", file_recv, sender);
+
+            channel
+        };
+
+        let window = Window::new(WindowType::Toplevel);
+
+        window.set_title("Discordant");
+        window.set_border_width(10);
+        window.set_position(WindowPosition::Center);
+        window.maximize();
+
+        let topmost_container = gtk::Box::new(Orientation::Horizontal, 0);
+        let leftmost_guild_list = gtk::Box::new(Orientation::Vertical, 2);
+        let left_channel_list = gtk::Box::new(Orientation::Vertical, 2);
+        let middle_chat = gtk::Box::new(Orientation::Vertical, 2);
+        let rightmost_member_list = gtk::Box::new(Orientation::Vertical, 2);
+        topmost_container.pack_start(&leftmost_guild_list, false, false, 0);
+        topmost_container.pack_start(&left_channel_list, false, false, 0);
+        topmost_container.pack_start(&middle_chat, true, true, 0);
+        topmost_container.pack_start(&rightmost_member_list, false, false, 0);
+
+        let (home_row, home_label) = home_row();
+        let home_list = gtk::ListBox::new();
+        home_list.add(&home_row);
+        leftmost_guild_list.pack_start(&home_list, false, false, 0);
+
+        let connecting = gtk::Spinner::new();
+        connecting.set_tooltip_text(Some("Connecting…"));
+        leftmost_guild_list.pack_start(&connecting, false, false, 6);
+
+        let guild_list = gtk::ListBox::new();
+        let mut guild_labels = HashMap::new();
+        for guild_id in guilds.iter() {
+            if let Some(guild) = cache.guild(*guild_id) {
+                let (row, label) = guild_row(&guild, &mut images);
+                guild_list.add(&row);
+                guild_labels.insert(*guild_id, label);
+            }
+        }
+        guild_list.show();
+        leftmost_guild_list.pack_start(&guild_list, true, true, 0);
+
+        let channel_list = ChannelList::new();
+        let dm_list = DmList::new();
+        let channel_stack = gtk::Stack::new();
+        channel_stack.add_named(channel_list.widget(), "channels");
+        channel_stack.add_named(dm_list.widget(), "dms");
+        left_channel_list.pack_start(&channel_stack, true, true, 0);
+
+        let user_panel = UserPanel::new();
+        match &state {
+            Some(state) => {
+                user_panel.set_user(&state.user.name, Some(state.user.face()), &mut images)
+            }
+            None => user_panel.set_user(&account_name(&accounts[0]), None, &mut images),
+        }
+        user_panel.set_presence(&discord.presence());
+        left_channel_list.pack_end(user_panel.widget(), false, false, 0);
+
+        let member_list = MemberList::new();
+        rightmost_member_list.pack_start(member_list.widget(), true, true, 0);
+        if let Some(adjustment) = member_list.widget().get_vadjustment() {
+            connect!(
+                relm,
+                adjustment,
+                connect_value_changed(_),
+                Some(Msg::MembersScrolled)
+            );
+            connect!(
+                relm,
+                adjustment,
+                connect_changed(_),
+                Some(Msg::MembersScrolled)
+            );
+        }
+
+        let chat = ChatView::new(relm.stream().clone());
+        middle_chat.pack_start(chat.widget(), true, true, 0);
+        // Files dropped on the chat are attached to the next message
+        chat.widget().drag_dest_set(
+            gtk::DestDefaults::ALL,
+            &[gtk::TargetEntry::new(
+                "text/uri-list",
+                gtk::TargetFlags::OTHER_APP,
+                0,
+            )],
+            gdk::DragAction::COPY,
+        );
+
+        let notifier = Notifier::new(relm.stream().clone());
+
+        let typing = TypingIndicator::new();
+        middle_chat.pack_start(typing.widget(), false, false, 0);
+
+        let compose = ComposeBox::new();
+        middle_chat.pack_start(compose.widget(), false, false, 0);
+
+        window.add(&topmost_container);
+        window.show_all();
+
+        connect!(
+            relm,
+            window,
+            connect_delete_event(_, _),
+            return (Some(Msg::Quit), Inhibit(false))
+        );
+        connect!(
+            relm,
+            home_list,
+            connect_row_selected(_, row),
+            row.as_ref().map(|_| Msg::HomeSelected)
+        );
+        connect!(
+            relm,
+            guild_list,
+            connect_row_selected(_, row),
+            row.as_ref()
+                .map(|row| Msg::GuildSelected(row.get_index() as usize))
+        );
+        connect!(
+            relm,
+            guild_list,
+            connect_button_press_event(list, event),
+            return (context_row(list, event).map(Msg::GuildMenu), Inhibit(false))
+        );
+        connect!(
+            relm,
+            channel_list.list(),
+            connect_row_selected(_, row),
+            row.as_ref()
+                .map(|row| Msg::ChannelSelected(row.get_index() as usize))
+        );
+        connect!(
+            relm,
+            channel_list.list(),
+            connect_button_press_event(list, event),
+            return (
+                context_row(list, event).map(Msg::ChannelMenu),
+                Inhibit(false)
+            )
+        );
+        connect!(
+            relm,
+            dm_list.list(),
+            connect_row_selected(_, row),
+            row.as_ref()
+                .map(|row| Msg::DmSelected(row.get_index() as usize))
+        );
+        connect!(
+            relm,
+            dm_list.list(),
+            connect_button_press_event(list, event),
+            return (context_row(list, event).map(Msg::DmMenu), Inhibit(false))
+        );
+        connect!(
+            relm,
+            dm_list.new_button(),
+            connect_clicked(_),
+            Some(Msg::NewDm)
+        );
+        connect!(
+            relm,
+            user_panel.menu_button(),
+            connect_clicked(_),
+            Some(Msg::PresenceMenu)
+        );
+        connect!(
+            relm,
+            compose.text_view(),
+            connect_key_press_event(text_view, key),
+            return compose::key_press(text_view, key)
+        );
+        if let Some(buffer) = compose.text_view().get_buffer() {
+            connect!(relm, buffer, connect_changed(_), Some(Msg::Composing));
+        }
+        connect!(
+            relm,
+            compose.attach_button(),
+            connect_clicked(_),
+            Some(Msg::AttachFiles)
+        );
+        connect!(
+            relm,
+            compose.emoji_button(),
+            connect_clicked(button),
+            Some(Msg::PickEmoji(
+                EmojiTarget::Compose,
+                button.clone().upcast()
+            ))
+        );
+        connect!(
+            relm,
+            chat.widget(),
+            connect_drag_data_received(_, _, _, _, data, _, _),
+            Some(Msg::FilesDropped(dropped_files(data)))
+        );
+
+        window.show_all();
+        set_connecting(&connecting, state.is_none());
+
+        if let Some(row) = guild_list.get_row_at_index(0) {
+            guild_list.select_row(Some(&row));
+        }
+
+        let mut win = Self {
+            relm: relm.clone(),
+            window,
+            account_id,
+            discord,
+            backend_channel,
+            _image_channel: image_channel,
+            images,
+            cache,
+            channel_list,
+            dm_list,
+            channel_stack,
+            member_list,
+            user_panel,
+            chat,
+            compose,
+            typing,
+            notifier,
+            last_typing: None,
+            home_list,
+            home_label,
+            home: false,
+            connecting,
+            guild_list,
+            guilds,
+            guild_labels,
+            selected_guild: None,
+            unread: HashMap::new(),
+            requested_members: HashSet::new(),
+            user_id: state
+                .as_ref()
+                .map_or(UserId(accounts[0].user_id), |state| state.user.id),
+            presences_changed: false,
+            accounts,
+            background: HashMap::new(),
+        };
+        for guild_id in win.guilds.clone() {
+            win.load_unread(guild_id);
+        }
+        win.load_dm_unread();
+        // The stored state may have been the only way of knowing whose account it is
+        if let Some(state) = &state {
+            win.account_ready(account_id, &state.user);
+        }
+
+        let kept = win
+            .accounts
+            .iter()
+            .skip(1)
+            .filter(|account| account.keep_connected)
+            .cloned()
+            .collect::<Vec<_>>();
+        for account in kept {
+            let session = win.connect(&account);
+            win.background.insert(account.id, session);
+        }
+
+        win
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InitializationState {
+    pub guilds: Vec<GuildState>,
+    pub private_channels: Vec<PrivateChannel>,
+    /// Missing from states stored before group DMs were kept
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    pub user: CurrentUser,
+}
+
+/// A guild along with the members, roles and channels the client starts out with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildState {
+    pub id: GuildId,
+    pub name: String,
+    pub icon_url: Option<String>,
+    pub owner_id: UserId,
+    pub members: Vec<Member>,
+    pub roles: HashMap<RoleId, Role>,
+    /// Missing from states stored before custom emoji were kept
+    #[serde(default)]
+    pub emojis: HashMap<EmojiId, Emoji>,
+    pub channels: HashMap<ChannelId, GuildChannel>,
+}
+
+impl GuildState {
+    pub fn from_partial(
+        guild: &PartialGuild,
+        members: Vec<Member>,
+        channels: HashMap<ChannelId, GuildChannel>,
+    ) -> Self {
+        Self {
+            id: guild.id,
+            name: guild.name.clone(),
+            icon_url: guild.icon_url(),
+            owner_id: guild.owner_id,
+            members,
+            roles: guild.roles.clone(),
+            emojis: guild.emojis.clone(),
+            channels,
+        }
+    }
+}
+
+impl From<&Guild> for GuildState {
+    fn from(guild: &Guild) -> Self {
+        Self {
+            id: guild.id,
+            name: guild.name.clone(),
+            icon_url: guild.icon_url(),
+            owner_id: guild.owner_id,
+            members: guild.members.values().cloned().collect(),
+            roles: guild.roles.clone(),
+            emojis: guild.emojis.clone(),
+            channels: guild
+                .channels
+                .iter()
+                .map(|(channel_id, channel)| (*channel_id, channel.read().clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The name an account is listed under, its user's name once it's been connected
+fn account_name(account: &Account) -> String {
+    if !account.name.is_empty() {
+        return account.name.clone();
+    }
+
+    match account.id {
+        backend::TEMPORARY_ID => "DISCORD_TOKEN".to_string(),
+        id => format!("Account {}", id + 1),
+    }
+}
+
+/// Emits the events of an account's backend as `Msg::Backend`, until the returned channel
+/// is dropped
+fn backend_channel(
+    relm: &Relm<Win>,
+    account_id: u64,
+    backend_recv: Receiver<BackendMsg>,
+) -> relm::Channel<BackendMsg> {
+    let stream = relm.stream().clone();
+    let (channel, sender) =
+        relm::Channel::new(move |msg| stream.emit(Msg::Backend(account_id, msg)));
+    pump("Backend Pump", backend_recv, sender);
+
+    channel
+}
+
+/// The channels with a newer message than the last one read. Channels which were never
+/// read before start out read, rather than everything being unread on the first launch
+fn unread_channels(
+    discord: &backend::Discord,
+    channels: Vec<(ChannelId, MessageId)>,
+) -> Vec<ChannelId> {
+    channels
+        .into_iter()
+        .filter(
+            |(channel_id, newest)| match discord.last_read(*channel_id) {
+                Some(read) => read < *newest,
+                None => {
+                    discord.mark_read(*channel_id, *newest);
+                    false
+                }
+            },
+        )
+        .map(|(channel_id, _)| channel_id)
+        .collect()
+}
+
+/// The newest message of every DM we know of
+fn dms_newest(cache: &Cache) -> Vec<(ChannelId, MessageId)> {
+    cache
+        .dm_ids()
+        .into_iter()
+        .filter_map(|channel_id| {
+            let newest = cache.dm(channel_id)?.last_message_id?;
+            Some((ChannelId(channel_id), MessageId(newest)))
+        })
+        .collect()
+}
+
+/// The newest message of every channel in a guild, unless the guild isn't cached
+fn guild_newest(cache: &Cache, guild_id: u64) -> Option<Vec<(ChannelId, MessageId)>> {
+    let guild = cache.guild(guild_id)?;
+    Some(
+        guild
+            .channels
+            .iter()
+            .filter_map(|(channel_id, channel)| {
+                Some((ChannelId(*channel_id), MessageId(channel.last_message_id?)))
+            })
+            .collect(),
+    )
+}
+
+/// Counts a new message as unread in its channel, along with whether it mentions the user
+fn count_unread(
+    unread: &mut HashMap<ChannelId, Unread>,
+    message: &Message,
+    cache: &Cache,
+    user_id: UserId,
+) {
+    let mentioned = mentions(message, cache, user_id);
+    let unread = unread.entry(message.channel_id).or_default();
+    unread.unread = true;
+    if mentioned {
+        unread.mentions += 1;
+    }
+}
+
+/// Whether a message someone else sent is to be notified about, going by its channel's
+/// notification level
+fn notifies(message: &Message, discord: &backend::Discord, cache: &Cache, user_id: UserId) -> bool {
+    if message.author.id == user_id {
+        return false;
+    }
+
+    match notify_level(discord, message) {
+        NotifyLevel::All => true,
+        NotifyLevel::Mentions => mentions(message, cache, user_id),
+        NotifyLevel::Nothing => false,
+    }
+}
+
+/// The notification level set for a message's channel, or else for its guild. Guilds
+/// default to only mentions, DMs to all messages
+fn notify_level(discord: &backend::Discord, message: &Message) -> NotifyLevel {
+    let default = match message.guild_id {
+        Some(guild_id) => discord
+            .notify_level(guild_id.0)
+            .unwrap_or(NotifyLevel::Mentions),
+        None => NotifyLevel::All,
+    };
+
+    discord
+        .notify_level(message.channel_id.0)
+        .unwrap_or(default)
+}
+
+/// Whether a message mentions the user, directly or through one of their roles
+fn mentions(message: &Message, cache: &Cache, user_id: UserId) -> bool {
+    let guild_id = match message.guild_id {
+        Some(guild_id) => guild_id,
+        // Everything sent to a DM is meant for us
+        None => return true,
+    };
+
+    let mentions_role = cache
+        .guild(guild_id.0)
+        .and_then(|guild| {
+            let member = guild.members.get(&user_id.0)?;
+            Some(
+                member
+                    .roles
+                    .iter()
+                    .any(|role_id| message.mention_roles.contains(&RoleId(*role_id))),
+            )
+        })
+        .unwrap_or(false);
+
+    message.mention_everyone
+        || message.mentions.iter().any(|user| user.id == user_id)
+        || mentions_role
+}
+
+/// The title and body of a message's notification, the body being a preview of the
+/// message as plain text
+fn notification(message: &Message, cache: &Cache) -> (String, String) {
+    let guild_id = message.guild_id.map(|guild_id| guild_id.0);
+    let author = cache
+        .display_name(guild_id, message.author.id.0)
+        .unwrap_or_else(|| message.author.name.clone());
+    let title = match guild_id.and_then(|guild_id| cache.guild(guild_id)) {
+        Some(guild) => {
+            let channel = guild
+                .channels
+                .get(&message.channel_id.0)
+                .map_or("", |channel| channel.name.as_str());
+            format!("{} (#{}, {})", author, channel, guild.name)
+        }
+        None => author,
+    };
+
+    let mut body = markdown::plain_text(&markdown::parse(&message.content), cache, guild_id);
+    if body.chars().count() > NOTIFICATION_PREVIEW {
+        body = body.chars().take(NOTIFICATION_PREVIEW).collect::<String>() + "…";
+    }
+    if body.is_empty() && !message.attachments.is_empty() {
+        body = "Sent an attachment".to_string();
+    }
+
+    (title, body)
+}
+
+/// A guild's row along with its name, which shows what's unread in the guild
+fn guild_row(guild: &GuildData, images: &mut ImageLoader) -> (gtk::Box, UnreadLabel) {
+    let guild_row = gtk::Box::new(Orientation::Horizontal, 0);
+    let icon = Avatar::new(&guild.name, GUILD_ICON_RADIUS);
+    if let Some(icon_url) = &guild.icon {
+        images.load(icon_url.clone(), &icon);
+    }
+    guild_row.add(icon.widget());
+    let label = UnreadLabel::new(&guild.name);
+    guild_row.add(label.widget());
+
+    (guild_row, label)
+}
+
+/// Home's row above the guilds, which shows what's unread in all DMs
+fn home_row() -> (gtk::Box, UnreadLabel) {
+    let home_row = gtk::Box::new(Orientation::Horizontal, 0);
+    let icon = Avatar::new("Home", GUILD_ICON_RADIUS);
+    home_row.add(icon.widget());
+    let label = UnreadLabel::new("Home");
+    home_row.add(label.widget());
+
+    (home_row, label)
+}
+
+fn set_connecting(spinner: &gtk::Spinner, connecting: bool) {
+    spinner.set_visible(connecting);
+    if connecting {
+        spinner.start();
+    } else {
+        spinner.stop();
+    }
+}
+
+/// The index of the row right-clicked for its context menu, if one was
+fn context_row(list: &gtk::ListBox, event: &gdk::EventButton) -> Option<usize> {
+    if event.get_event_type() != gdk::EventType::ButtonPress || event.get_button() != 3 {
+        return None;
+    }
+
+    let (_, y) = event.get_position();
+    list.get_row_at_y(y as i32)
+        .map(|row| row.get_index() as usize)
+}
+
+/// The local files among those dragged onto the window
+fn dropped_files(data: &gtk::SelectionData) -> Vec<PathBuf> {
+    data.get_uris()
+        .iter()
+        .filter_map(|uri| match glib::filename_from_uri(uri) {
+            Ok((path, _)) => Some(path),
+            Err(err) => {
+                eprintln!("Drop Error: {:?}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Forwards everything received on a backend channel into the GTK main loop
+fn pump<T: Send + 'static>(name: &str, mut recv: Receiver<T>, sender: relm::Sender<T>) {
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            futures::executor::block_on(async move {
+                while let Some(msg) = recv.next().await {
+                    if sender.send(msg).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .unwrap_or_else(|_| panic!("Failed to spawn {} thread", name));
+}
@@ -0,0 +1,137 @@
+use super::Msg;
+use crate::backend::NotifyLevel;
+use gio::{ActionMapExt, ApplicationExt, SimpleActionExt};
+use gtk::{CheckMenuItemExt, GtkMenuItemExt, MenuShellExt};
+use relm::EventStream;
+use serenity::model::id::{ChannelId, MessageId};
+use std::rc::Rc;
+
+const APPLICATION_ID: &str = "org.discordant.Discordant";
+/// Clicking a notification activates this action with the account, channel and message
+/// it's for
+const OPEN_ACTION: &str = "open-message";
+
+/// Sends desktop notifications through the session's notification server, clicking one
+/// emits `Msg::OpenMessage`
+pub struct Notifier {
+    app: Option<gio::Application>,
+}
+
+impl Notifier {
+    /// Registers the client with the session, notifications aren't sent if that fails
+    pub fn new(stream: EventStream<Msg>) -> Self {
+        let app = gio::Application::new(Some(APPLICATION_ID), gio::ApplicationFlags::FLAGS_NONE);
+
+        let action = gio::SimpleAction::new(OPEN_ACTION, glib::VariantTy::new("s").ok());
+        action.connect_activate(move |_, target| {
+            let target = target.as_ref().and_then(|target| target.get_str());
+            if let Some((account_id, channel_id, message_id)) = target.and_then(parse_target) {
+                stream.emit(Msg::OpenMessage(account_id, channel_id, message_id));
+            }
+        });
+        app.add_action(&action);
+
+        if let Err(err) = app.register(None::<&gio::Cancellable>) {
+            eprintln!("Notification Error: {:?}", err);
+            return Self { app: None };
+        }
+
+        Self { app: Some(app) }
+    }
+
+    /// Shows a message, replacing the previous notification of its channel
+    pub fn notify(
+        &self,
+        account_id: u64,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        title: &str,
+        body: &str,
+        icon: Option<gdk_pixbuf::Pixbuf>,
+    ) {
+        let app = match &self.app {
+            Some(app) => app,
+            None => return,
+        };
+
+        let notification = gio::Notification::new(title);
+        notification.set_body(Some(body));
+        if let Some(icon) = icon {
+            notification.set_icon(&icon);
+        }
+        let target = format!("{}/{}/{}", account_id, channel_id.0, message_id.0);
+        let target = glib::Variant::from(target.as_str());
+        notification
+            .set_default_action_and_target_value(&format!("app.{}", OPEN_ACTION), Some(&target));
+
+        app.send_notification(Some(&notification_id(channel_id)), &notification);
+    }
+
+    /// Takes back the notification of a channel once it's been read
+    pub fn withdraw(&self, channel_id: ChannelId) {
+        if let Some(app) = &self.app {
+            app.withdraw_notification(&notification_id(channel_id));
+        }
+    }
+}
+
+/// A submenu to pick the notification level of a guild or channel from, which can also
+/// be left at its default
+pub fn level_item(
+    current: Option<NotifyLevel>,
+    default: NotifyLevel,
+    on_select: impl Fn(Option<NotifyLevel>) + 'static,
+) -> gtk::MenuItem {
+    let on_select = Rc::new(on_select);
+    let menu = gtk::Menu::new();
+    let choices = [
+        (None, format!("Default ({})", level_name(default))),
+        (
+            Some(NotifyLevel::All),
+            level_name(NotifyLevel::All).to_string(),
+        ),
+        (
+            Some(NotifyLevel::Mentions),
+            level_name(NotifyLevel::Mentions).to_string(),
+        ),
+        (
+            Some(NotifyLevel::Nothing),
+            level_name(NotifyLevel::Nothing).to_string(),
+        ),
+    ];
+    for (level, label) in choices.iter() {
+        let choice = gtk::CheckMenuItem::new_with_label(label);
+        choice.set_draw_as_radio(true);
+        choice.set_active(*level == current);
+
+        let (on_select, level) = (Rc::clone(&on_select), *level);
+        choice.connect_activate(move |_| on_select(level));
+        menu.append(&choice);
+    }
+
+    let item = gtk::MenuItem::new_with_label("Notifications");
+    item.set_submenu(Some(&menu));
+
+    item
+}
+
+fn level_name(level: NotifyLevel) -> &'static str {
+    match level {
+        NotifyLevel::All => "All Messages",
+        NotifyLevel::Mentions => "Only Mentions",
+        NotifyLevel::Nothing => "Nothing",
+    }
+}
+
+fn notification_id(channel_id: ChannelId) -> String {
+    format!("channel-{}", channel_id.0)
+}
+
+fn parse_target(target: &str) -> Option<(u64, ChannelId, MessageId)> {
+    let mut ids = target.split('/').map(str::parse);
+    let account_id = ids.next()?.ok()?;
+    let channel_id = ids.next()?.ok()?;
+    let message_id = ids.next()?.ok()?;
+
+    Some((account_id, ChannelId(channel_id), MessageId(message_id)))
+}
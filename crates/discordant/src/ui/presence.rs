@@ -0,0 +1,35 @@
+use serenity::model::user::OnlineStatus;
+
+/// A dot in the colour of the status followed by its name, as pango markup
+pub fn status_markup(status: OnlineStatus) -> String {
+    let name = match status {
+        OnlineStatus::Online => "Online",
+        OnlineStatus::Idle => "Idle",
+        OnlineStatus::DoNotDisturb => "Do Not Disturb",
+        // Only ever our own status, others look offline while invisible
+        OnlineStatus::Invisible => "Invisible",
+        _ => "Offline",
+    };
+
+    format!("{} {}", status_dot(status), name)
+}
+
+/// Only the dot of `status_markup`
+pub fn status_dot(status: OnlineStatus) -> String {
+    let color = match status {
+        OnlineStatus::Online => "#43b581",
+        OnlineStatus::Idle => "#faa61a",
+        OnlineStatus::DoNotDisturb => "#f04747",
+        _ => "#747f8d",
+    };
+
+    format!("<span foreground=\"{}\">●</span>", color)
+}
+
+/// Whether the status shows the user as around, invisible users look offline
+pub fn is_online(status: OnlineStatus) -> bool {
+    match status {
+        OnlineStatus::Offline | OnlineStatus::Invisible => false,
+        _ => true,
+    }
+}
@@ -0,0 +1,109 @@
+use glib::ObjectExt;
+use gtk::{LabelExt, StyleContextExt, WidgetExt};
+use serenity::model::id::{ChannelId, UserId};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Discord considers someone to have stopped typing this long after they started, unless
+/// they start again
+const TYPING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Who else is typing in the current channel, shown below the chat pane
+pub struct TypingIndicator {
+    label: gtk::Label,
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    channel: Option<ChannelId>,
+    /// The names of the users typing in each channel and when they started
+    typing: HashMap<ChannelId, HashMap<UserId, (String, Instant)>>,
+}
+
+impl TypingIndicator {
+    pub fn new() -> Self {
+        let label = gtk::Label::new(None);
+        label.set_xalign(0.0);
+        label.set_ellipsize(pango::EllipsizeMode::End);
+        label.get_style_context().add_class("dim-label");
+
+        Self {
+            label,
+            state: Rc::new(RefCell::new(State::default())),
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Label {
+        &self.label
+    }
+
+    pub fn set_channel(&self, channel_id: ChannelId) {
+        self.state.borrow_mut().channel = Some(channel_id);
+        render(&self.label, &self.state);
+    }
+
+    /// Shows the user as typing until they stop or send a message
+    pub fn start(&self, channel_id: ChannelId, user_id: UserId, name: String) {
+        self.state
+            .borrow_mut()
+            .typing
+            .entry(channel_id)
+            .or_default()
+            .insert(user_id, (name, Instant::now()));
+        render(&self.label, &self.state);
+
+        let (label, state) = (self.label.downgrade(), Rc::downgrade(&self.state));
+        gtk::timeout_add(TYPING_TIMEOUT.as_millis() as u32, move || {
+            if let (Some(label), Some(state)) = (label.upgrade(), state.upgrade()) {
+                render(&label, &state);
+            }
+
+            glib::Continue(false)
+        });
+    }
+
+    /// Sending a message ends typing
+    pub fn stop(&self, channel_id: ChannelId, user_id: UserId) {
+        if let Some(typing) = self.state.borrow_mut().typing.get_mut(&channel_id) {
+            typing.remove(&user_id);
+        }
+        render(&self.label, &self.state);
+    }
+}
+
+/// Forgets whoever stopped typing and lists the rest for the current channel
+fn render(label: &gtk::Label, state: &RefCell<State>) {
+    let mut state = state.borrow_mut();
+    for typing in state.typing.values_mut() {
+        typing.retain(|_, (_, started)| started.elapsed() < TYPING_TIMEOUT);
+    }
+    state.typing.retain(|_, typing| !typing.is_empty());
+
+    let mut names = state
+        .channel
+        .and_then(|channel_id| state.typing.get(&channel_id))
+        .map(|typing| {
+            typing
+                .values()
+                .map(|(name, started)| (name.as_str(), *started))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort_by_key(|(_, started)| *started);
+
+    let text = match names.as_slice() {
+        [] => String::new(),
+        [(name, _)] => format!("{} is typing…", name),
+        [(first, _), (second, _)] => format!("{} and {} are typing…", first, second),
+        [(first, _), (second, _), (third, _)] => {
+            format!("{}, {} and {} are typing…", first, second, third)
+        }
+        _ => "Several people are typing…".to_string(),
+    };
+    label.set_text(&text);
+}
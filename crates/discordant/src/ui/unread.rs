@@ -0,0 +1,79 @@
+use gtk::{BoxExt, GtkMenuItemExt, LabelExt, Orientation, WidgetExt};
+
+/// What hasn't been read yet in a channel, or in all channels of a guild
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Unread {
+    pub unread: bool,
+    /// How many of the unread messages mention us
+    pub mentions: u32,
+}
+
+impl Unread {
+    /// Adds up the unread state of several channels
+    pub fn sum(unread: impl IntoIterator<Item = Unread>) -> Self {
+        unread
+            .into_iter()
+            .fold(Self::default(), |sum, unread| Self {
+                unread: sum.unread || unread.unread,
+                mentions: sum.mentions + unread.mentions,
+            })
+    }
+}
+
+/// A name which is bold while there's anything unread, followed by a badge with the
+/// number of unread mentions
+pub struct UnreadLabel {
+    container: gtk::Box,
+    name: gtk::Label,
+    badge: gtk::Label,
+    text: String,
+}
+
+impl UnreadLabel {
+    pub fn new(text: &str) -> Self {
+        let name = gtk::Label::new(Some(text));
+        name.set_xalign(0.0);
+        name.set_ellipsize(pango::EllipsizeMode::End);
+
+        let badge = gtk::Label::new(None);
+        badge.set_no_show_all(true);
+
+        let container = gtk::Box::new(Orientation::Horizontal, 4);
+        container.pack_start(&name, true, true, 0);
+        container.pack_start(&badge, false, false, 0);
+
+        Self {
+            container,
+            name,
+            badge,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    pub fn set(&self, unread: Unread) {
+        if unread.unread {
+            self.name
+                .set_markup(&format!("<b>{}</b>", glib::markup_escape_text(&self.text)));
+        } else {
+            self.name.set_text(&self.text);
+        }
+
+        self.badge.set_markup(&format!(
+            "<span background=\"#f04747\" foreground=\"white\"><b> {} </b></span>",
+            unread.mentions
+        ));
+        self.badge.set_visible(unread.mentions > 0);
+    }
+}
+
+/// A context menu item to mark something as read
+pub fn mark_read_item(on_activate: impl Fn() + 'static) -> gtk::MenuItem {
+    let item = gtk::MenuItem::new_with_label("Mark as Read");
+    item.connect_activate(move |_| on_activate());
+
+    item
+}
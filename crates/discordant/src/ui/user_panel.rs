@@ -0,0 +1,230 @@
+use super::{
+    images::{Avatar, ImageLoader},
+    presence,
+};
+use crate::backend::OwnPresence;
+use gtk::{
+    BoxExt, CheckMenuItemExt, ContainerExt, DialogExt, EntryExt, GtkMenuItemExt, GtkWindowExt,
+    LabelExt, MenuShellExt, Orientation, WidgetExt,
+};
+use serenity::model::user::OnlineStatus;
+use std::rc::Rc;
+
+const AVATAR_RADIUS: f64 = 16.0;
+
+/// Our own avatar, name and presence, below the channel list
+pub struct UserPanel {
+    container: gtk::Box,
+    avatar: gtk::Box,
+    name: gtk::Label,
+    presence: gtk::Label,
+    menu_button: gtk::Button,
+}
+
+impl UserPanel {
+    pub fn new() -> Self {
+        let avatar = gtk::Box::new(Orientation::Horizontal, 0);
+
+        let name = gtk::Label::new(None);
+        name.set_xalign(0.0);
+        name.set_ellipsize(pango::EllipsizeMode::End);
+        let presence = gtk::Label::new(None);
+        presence.set_xalign(0.0);
+        presence.set_ellipsize(pango::EllipsizeMode::End);
+
+        let text = gtk::Box::new(Orientation::Vertical, 0);
+        text.set_valign(gtk::Align::Center);
+        text.pack_start(&name, false, false, 0);
+        text.pack_start(&presence, false, false, 0);
+
+        let menu_button =
+            gtk::Button::new_from_icon_name(Some("emblem-system-symbolic"), gtk::IconSize::Button);
+        menu_button.set_relief(gtk::ReliefStyle::None);
+        menu_button.set_tooltip_text(Some("Status and accounts"));
+
+        let container = gtk::Box::new(Orientation::Horizontal, 6);
+        container.pack_start(&avatar, false, false, 0);
+        container.pack_start(&text, true, true, 0);
+        container.pack_start(&menu_button, false, false, 0);
+
+        Self {
+            container,
+            avatar,
+            name,
+            presence,
+            menu_button,
+        }
+    }
+
+    pub fn widget(&self) -> &gtk::Box {
+        &self.container
+    }
+
+    /// Pops up the menu to set our presence from
+    pub fn menu_button(&self) -> &gtk::Button {
+        &self.menu_button
+    }
+
+    /// Shows whose account we're using, the avatar's initials stand in until it's known
+    pub fn set_user(&self, name: &str, avatar_url: Option<String>, images: &mut ImageLoader) {
+        for child in self.avatar.get_children() {
+            self.avatar.remove(&child);
+        }
+
+        let avatar = Avatar::new(name, AVATAR_RADIUS);
+        if let Some(avatar_url) = avatar_url {
+            images.load(avatar_url, &avatar);
+        }
+        self.avatar.add(avatar.widget());
+        self.avatar.show_all();
+
+        self.name.set_text(name);
+    }
+
+    pub fn set_presence(&self, presence: &OwnPresence) {
+        let mut markup = presence::status_markup(presence.status);
+        if let Some(activity) = &presence.activity {
+            markup += &format!(" · Playing {}", glib::markup_escape_text(activity));
+        }
+
+        self.presence.set_markup(&markup);
+    }
+}
+
+/// A menu to pick our status from, or to set or clear our activity
+pub fn presence_menu(
+    current: &OwnPresence,
+    on_select: impl Fn(OwnPresence) + 'static,
+    on_edit_activity: impl Fn() + 'static,
+) -> gtk::Menu {
+    let on_select = Rc::new(on_select);
+    let menu = gtk::Menu::new();
+
+    let statuses = [
+        OnlineStatus::Online,
+        OnlineStatus::Idle,
+        OnlineStatus::DoNotDisturb,
+        OnlineStatus::Invisible,
+    ];
+    for status in statuses.iter().cloned() {
+        let choice = gtk::CheckMenuItem::new();
+        let label = gtk::Label::new(None);
+        label.set_markup(&presence::status_markup(status));
+        label.set_xalign(0.0);
+        choice.add(&label);
+        choice.set_draw_as_radio(true);
+        choice.set_active(status == current.status);
+
+        let (on_select, presence) = (
+            Rc::clone(&on_select),
+            OwnPresence {
+                status,
+                ..current.clone()
+            },
+        );
+        choice.connect_activate(move |_| on_select(presence.clone()));
+        menu.append(&choice);
+    }
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    let edit = gtk::MenuItem::new_with_label("Set Activity…");
+    edit.connect_activate(move |_| on_edit_activity());
+    menu.append(&edit);
+
+    if current.activity.is_some() {
+        let presence = OwnPresence {
+            activity: None,
+            ..current.clone()
+        };
+        let clear = gtk::MenuItem::new_with_label("Clear Activity");
+        clear.connect_activate(move |_| on_select(presence.clone()));
+        menu.append(&clear);
+    }
+
+    menu
+}
+
+/// Another account we're logged in to, as offered in the account items
+pub struct OtherAccount {
+    pub id: u64,
+    pub name: String,
+    /// Only known for accounts kept connected
+    pub mentions: u32,
+}
+
+/// Appends the items to switch to another account, to add one, to keep the current one
+/// connected after switching away from it, or to log out of it
+pub fn account_items(
+    menu: &gtk::Menu,
+    others: &[OtherAccount],
+    keep_connected: bool,
+    on_switch: impl Fn(u64) + 'static,
+    on_add: impl Fn() + 'static,
+    on_keep_connected: impl Fn(bool) + 'static,
+    on_log_out: impl Fn() + 'static,
+) {
+    let on_switch = Rc::new(on_switch);
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    for other in others {
+        let mut label = format!("Switch to {}", other.name);
+        if other.mentions > 0 {
+            label += &format!(" ({})", other.mentions);
+        }
+        let switch = gtk::MenuItem::new_with_label(&label);
+
+        let (on_switch, id) = (Rc::clone(&on_switch), other.id);
+        switch.connect_activate(move |_| on_switch(id));
+        menu.append(&switch);
+    }
+
+    let add = gtk::MenuItem::new_with_label("Add Account…");
+    add.connect_activate(move |_| on_add());
+    menu.append(&add);
+
+    let keep = gtk::CheckMenuItem::new_with_label("Stay Connected in Background");
+    keep.set_active(keep_connected);
+    keep.connect_toggled(move |keep| on_keep_connected(keep.get_active()));
+    menu.append(&keep);
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    let log_out = gtk::MenuItem::new_with_label("Log Out");
+    log_out.connect_activate(move |_| on_log_out());
+    menu.append(&log_out);
+}
+
+/// Asks for what we're playing, starting out with the current activity. An empty text
+/// clears it, cancelling gives `None`
+pub fn ask_activity(parent: &gtk::Window, current: Option<&str>) -> Option<String> {
+    let dialog = gtk::Dialog::new_with_buttons(
+        Some("Set Activity"),
+        Some(parent),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let entry = gtk::Entry::new();
+    entry.set_placeholder_text(Some("Playing…"));
+    entry.set_text(current.unwrap_or(""));
+    entry.set_activates_default(true);
+    dialog
+        .get_content_area()
+        .pack_start(&entry, false, false, 0);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let text = entry.get_text().map(|text| text.trim().to_string());
+    dialog.destroy();
+
+    if response == gtk::ResponseType::Accept.into() {
+        Some(text.unwrap_or_default())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,81 @@
+use gtk::{
+    BoxExt, ContainerExt, DialogExt, EntryExt, GtkWindowExt, LabelExt, ListBoxExt, ListBoxRowExt,
+    ScrolledWindowExt, SearchEntryExt, WidgetExt,
+};
+use std::{cell::RefCell, rc::Rc};
+
+const PICKER_WIDTH: i32 = 320;
+const PICKER_HEIGHT: i32 = 400;
+
+/// Asks to pick one of the given users, by id and name, filtered by typing part of their
+/// name. Blocks until a user is picked or the dialog is cancelled
+pub fn pick_user(parent: &gtk::Window, title: &str, users: Vec<(u64, String)>) -> Option<u64> {
+    let mut users = users;
+    users.sort_by_key(|(_, name)| name.to_lowercase());
+    let users = Rc::new(users);
+
+    let dialog = gtk::Dialog::new_with_buttons(
+        Some(title),
+        Some(parent),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Select", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_size(PICKER_WIDTH, PICKER_HEIGHT);
+
+    let list = gtk::ListBox::new();
+    for (_, name) in users.iter() {
+        let label = gtk::Label::new(Some(name.as_str()));
+        label.set_xalign(0.0);
+        list.add(&label);
+    }
+
+    let query = Rc::new(RefCell::new(String::new()));
+    let (filter_query, filter_users) = (Rc::clone(&query), Rc::clone(&users));
+    list.set_filter_func(Some(Box::new(move |row| {
+        filter_users
+            .get(row.get_index() as usize)
+            .map_or(false, |(_, name)| {
+                name.to_lowercase().contains(&*filter_query.borrow())
+            })
+    })));
+
+    let search = gtk::SearchEntry::new();
+    search.set_placeholder_text(Some("Search by name"));
+    let search_list = list.clone();
+    search.connect_search_changed(move |search| {
+        let text = search.get_text().map(|text| text.to_lowercase());
+        *query.borrow_mut() = text.unwrap_or_default();
+        search_list.invalidate_filter();
+    });
+
+    // Double clicking or pressing enter on a user picks them
+    let activate_dialog = dialog.clone();
+    list.connect_row_activated(move |_, _| {
+        activate_dialog.response(gtk::ResponseType::Accept);
+    });
+
+    let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scrolled.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled.add(&list);
+
+    let content = dialog.get_content_area();
+    content.pack_start(&search, false, false, 0);
+    content.pack_start(&scrolled, true, true, 0);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let picked = list
+        .get_selected_row()
+        .and_then(|row| users.get(row.get_index() as usize))
+        .map(|(user_id, _)| *user_id);
+    dialog.destroy();
+
+    if response == gtk::ResponseType::Accept.into() {
+        picked
+    } else {
+        None
+    }
+}
@@ -0,0 +1,97 @@
+use super::images::{Avatar, ImageLoader};
+use gtk::{
+    BoxExt, ButtonExt, ContainerExt, GtkWindowExt, Inhibit, LabelExt, Orientation,
+    ScrolledWindowExt, WidgetExt,
+};
+use serenity::model::channel::Attachment;
+use std::rc::Rc;
+
+const DEFAULT_WIDTH: i32 = 1024;
+const DEFAULT_HEIGHT: i32 = 768;
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 8.0;
+/// Each step zooms in or out by this factor
+const ZOOM_STEP: f64 = 1.25;
+
+/// Opens an image attachment at full size in a window of its own. Large images start out
+/// fitted to the window and can be zoomed with the buttons or Ctrl+scroll
+pub fn show(parent: &gtk::Window, attachment: &Attachment, images: &mut ImageLoader) {
+    let (width, height) = match attachment.dimensions() {
+        Some(dimensions) => dimensions,
+        None => return,
+    };
+
+    let picture = Avatar::sized(&attachment.filename, width as f64, height as f64);
+    picture.widget().set_halign(gtk::Align::Center);
+    picture.widget().set_valign(gtk::Align::Center);
+    images.load(attachment.proxy_url.clone(), &picture);
+
+    let zoom_label = gtk::Label::new(None);
+    let set_zoom = {
+        let (picture, zoom_label) = (picture.clone(), zoom_label.clone());
+        Rc::new(move |zoom: f64| {
+            let zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+            picture.set_zoom(zoom);
+            zoom_label.set_text(&format!("{:.0}%", zoom * 100.0));
+        })
+    };
+    set_zoom(
+        (f64::from(DEFAULT_WIDTH) / width as f64)
+            .min(f64::from(DEFAULT_HEIGHT) / height as f64)
+            .min(1.0),
+    );
+
+    let toolbar = gtk::Box::new(Orientation::Horizontal, 4);
+    toolbar.set_halign(gtk::Align::Center);
+    let buttons = [
+        ("zoom-out-symbolic", "Zoom out", Some(1.0 / ZOOM_STEP)),
+        ("zoom-original-symbolic", "Actual size", None),
+        ("zoom-in-symbolic", "Zoom in", Some(ZOOM_STEP)),
+    ];
+    for (index, (icon, tooltip, factor)) in buttons.iter().cloned().enumerate() {
+        let button = gtk::Button::new_from_icon_name(Some(icon), gtk::IconSize::Button);
+        button.set_tooltip_text(Some(tooltip));
+
+        let (set_zoom, picture) = (Rc::clone(&set_zoom), picture.clone());
+        button.connect_clicked(move |_| match factor {
+            Some(factor) => set_zoom(picture.zoom() * factor),
+            None => set_zoom(1.0),
+        });
+        toolbar.pack_start(&button, false, false, 0);
+
+        // The zoom level sits between zooming out and in
+        if index == 0 {
+            toolbar.pack_start(&zoom_label, false, false, 6);
+        }
+    }
+
+    let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    scrolled.add(picture.widget());
+    scrolled.connect_scroll_event(move |_, event| {
+        if !event.get_state().contains(gdk::ModifierType::CONTROL_MASK) {
+            return Inhibit(false);
+        }
+
+        let factor = match event.get_direction() {
+            gdk::ScrollDirection::Up => ZOOM_STEP,
+            gdk::ScrollDirection::Down => 1.0 / ZOOM_STEP,
+            gdk::ScrollDirection::Smooth => ZOOM_STEP.powf(-event.get_delta().1),
+            _ => return Inhibit(false),
+        };
+        set_zoom(picture.zoom() * factor);
+
+        Inhibit(true)
+    });
+
+    let container = gtk::Box::new(Orientation::Vertical, 4);
+    container.pack_start(&toolbar, false, false, 0);
+    container.pack_start(&scrolled, true, true, 0);
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(&attachment.filename);
+    window.set_transient_for(Some(parent));
+    window.set_default_size(DEFAULT_WIDTH, DEFAULT_HEIGHT);
+    window.add(&container);
+    window.show_all();
+}